@@ -0,0 +1,262 @@
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::traktor::keys::KeyNotation;
+
+// The client shipped in the D2 folder, the base url is replaced on export
+const API_CLIENT_JS: &str = include_str!("../../traktor_api/D2/Api/ApiClient.js");
+
+/// Shows traktor's effects and decks on the Ableton Push 2 display
+#[derive(Parser, Debug, Clone)]
+#[command(version, about)]
+pub struct Config {
+    /// Address the traktor api server listens on
+    #[arg(long, env = "PUSH2TRAKTOR_HOST", default_value = "127.0.0.1")]
+    pub host: IpAddr,
+
+    /// Port the traktor api server listens on, has to match ApiClient.js
+    #[arg(long, short, env = "PUSH2TRAKTOR_PORT", default_value_t = 8080)]
+    pub port: u16,
+
+    /// Append every request from traktor to this file, see the replay command
+    #[arg(long, env = "PUSH2TRAKTOR_RECORD")]
+    pub record: Option<PathBuf>,
+
+    /// Keep a text file with the track on air up to date, e.g. for OBS.
+    /// The template defaults to "{artist} – {title}", can be repeated
+    #[arg(long, value_name = "FILE[=TEMPLATE]", value_parser = NowPlayingFile::parse)]
+    pub now_playing: Vec<NowPlayingFile>,
+
+    /// Save the state to this file and restore it on startup, so a restart
+    /// mid-set keeps the loaded tracks and effect names
+    #[arg(long, env = "PUSH2TRAKTOR_STATE_FILE")]
+    pub state_file: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub display: DisplaySettings,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// What the push display shows and how
+#[derive(Args, Debug, Clone, PartialEq)]
+#[command(next_help_heading = "Display")]
+pub struct DisplaySettings {
+    /// Bars per phrase for the countdown to the next phrase
+    #[arg(long, env = "PUSH2TRAKTOR_PHRASE_BARS", default_value_t = 16,
+          value_parser = clap::value_parser!(u32).range(1..))]
+    pub phrase_bars: u32,
+
+    /// How keys are written, next to each key the match with
+    /// the master deck is shown
+    #[arg(long, env = "PUSH2TRAKTOR_KEY_NOTATION", value_enum, default_value_t = KeyNotation::Musical)]
+    pub key_notation: KeyNotation,
+
+    /// Range of the pitch faders in percent, the tempo of decks which
+    /// can't reach the master tempo within it is highlighted
+    #[arg(long, env = "PUSH2TRAKTOR_PITCH_RANGE", default_value_t = 8.0, value_parser = parse_positive)]
+    pub pitch_range: f64,
+
+    /// Seconds before the end of a track its deck starts to flash, 0 turns it off
+    #[arg(long, env = "PUSH2TRAKTOR_END_WARNING", default_value_t = 30)]
+    pub end_warning: u32,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Writes an ApiClient.js which sends to the configured address,
+    /// replace the one in traktor's D2/Api folder with it
+    ApiClient {
+        /// File to write, printed if omitted
+        #[arg(long, short)]
+        out: Option<PathBuf>,
+
+        /// Base url traktor should send to, defaults to the configured host and port
+        #[arg(long)]
+        url: Option<String>,
+    },
+
+    /// Sends a session recorded with --record to a running driver
+    /// at the configured address
+    Replay {
+        /// Session file to replay
+        file: PathBuf,
+
+        /// Playback speed, 2 replays the session twice as fast
+        #[arg(long, short, default_value_t = 1.0, value_parser = parse_positive)]
+        speed: f64,
+    },
+
+    /// Sends made up traktor traffic to a running driver at the configured
+    /// address, for development without traktor
+    Simulate,
+}
+
+/// Text file the now playing track is written to
+#[derive(Debug, Clone, PartialEq)]
+pub struct NowPlayingFile {
+    pub path: PathBuf,
+    // Placeholders are {artist}, {title}, {album}, {label}, {remixer},
+    // {mix}, {genre}, {key}, {bpm} and {deck}
+    pub template: String,
+}
+
+impl NowPlayingFile {
+    fn parse(s: &str) -> Result<Self, String> {
+        let (path, template) = s.split_once('=').unwrap_or((s, "{artist} – {title}"));
+        if path.is_empty() {
+            return Err("The file name is missing".to_string());
+        }
+        Ok(Self {
+            path: PathBuf::from(path),
+            template: template.to_string(),
+        })
+    }
+}
+
+fn parse_positive(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(value) if value > 0.0 && value.is_finite() => Ok(value),
+        _ => Err(format!("'{}' is not a positive number", s)),
+    }
+}
+
+impl Config {
+    /// Address the traktor api (and other clients) can reach the server at
+    pub fn api_addr(&self) -> SocketAddr {
+        // Listening on all interfaces, traktor runs on the same machine
+        let host = if self.host.is_unspecified() {
+            IpAddr::from([127, 0, 0, 1])
+        } else {
+            self.host
+        };
+        SocketAddr::new(host, self.port)
+    }
+
+    pub fn api_base_url(&self) -> String {
+        // Also brackets ipv6 addresses
+        format!("http://{}", self.api_addr())
+    }
+
+    /// Contents of an ApiClient.js which sends its requests to `url`
+    pub fn api_client_js(url: &str) -> String {
+        API_CLIENT_JS
+            .lines()
+            .map(|line| {
+                if line.starts_with("var API_BASE_URL") {
+                    format!("var API_BASE_URL = \"{}\"", url)
+                } else {
+                    line.to_string()
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+            + "\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_api_base_url() {
+        let config = Config::parse_from(["push2display2traktor", "--port", "9000"]);
+        assert_eq!(config.api_base_url(), "http://127.0.0.1:9000");
+
+        let config = Config::parse_from(["push2display2traktor", "--host", "0.0.0.0"]);
+        assert_eq!(config.api_base_url(), "http://127.0.0.1:8080");
+
+        let config = Config::parse_from(["push2display2traktor", "--host", "::1"]);
+        assert_eq!(config.api_base_url(), "http://[::1]:8080");
+    }
+
+    #[test]
+    fn test_replay_speed() {
+        let config = Config::parse_from(["push2display2traktor", "replay", "set.jsonl", "-s", "4"]);
+        assert!(matches!(config.command, Some(Command::Replay { speed, .. }) if speed == 4.0));
+
+        for speed in ["0", "-1", "fast"] {
+            let res = Config::try_parse_from([
+                "push2display2traktor",
+                "replay",
+                "set.jsonl",
+                "-s",
+                speed,
+            ]);
+            assert!(res.is_err());
+        }
+    }
+
+    #[test]
+    fn test_display_settings() {
+        let config = Config::parse_from(["push2display2traktor"]);
+        assert_eq!(
+            config.display,
+            DisplaySettings {
+                phrase_bars: 16,
+                key_notation: KeyNotation::Musical,
+                pitch_range: 8.0,
+                end_warning: 30,
+            }
+        );
+
+        let config = Config::parse_from(["push2display2traktor", "--key-notation", "open-key"]);
+        assert_eq!(config.display.key_notation, KeyNotation::OpenKey);
+
+        let res = Config::try_parse_from(["push2display2traktor", "--key-notation", "german"]);
+        assert!(res.is_err());
+
+        let config = Config::parse_from(["push2display2traktor", "--pitch-range", "16"]);
+        assert_eq!(config.display.pitch_range, 16.0);
+        assert!(Config::try_parse_from(["push2display2traktor", "--pitch-range", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_now_playing_files() {
+        let config = Config::parse_from([
+            "push2display2traktor",
+            "--now-playing",
+            "np.txt",
+            "--now-playing",
+            "title.txt={title} [{bpm}]",
+        ]);
+        assert_eq!(
+            config.now_playing,
+            vec![
+                NowPlayingFile {
+                    path: PathBuf::from("np.txt"),
+                    template: "{artist} – {title}".to_string(),
+                },
+                NowPlayingFile {
+                    path: PathBuf::from("title.txt"),
+                    template: "{title} [{bpm}]".to_string(),
+                },
+            ]
+        );
+        assert!(
+            Config::try_parse_from(["push2display2traktor", "--now-playing", "={title}"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_api_client_js() {
+        let js = Config::api_client_js("http://192.168.1.10:9000");
+        assert!(js.starts_with("var API_BASE_URL = \"http://192.168.1.10:9000\"\n"));
+        assert!(js.contains("function send(endpoint, data)"));
+        assert!(!js.contains("8080"));
+    }
+
+    #[test]
+    fn test_api_client_js_protocol_version() {
+        let version = format!(
+            "var PROTOCOL_VERSION = {}\n",
+            crate::traktor::PROTOCOL_VERSION
+        );
+        assert!(API_CLIENT_JS.contains(&version));
+    }
+}
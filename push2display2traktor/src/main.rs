@@ -1,46 +1,100 @@
 #![feature(portable_simd)]
+mod config;
+mod metrics;
 mod push2;
 mod render;
 mod traktor;
 
+use crate::config::{Command, Config};
+use crate::metrics::Metrics;
 use crate::push2::Push2Display;
 use crate::render::render_loop;
-use crate::traktor::TraktorState;
+use crate::traktor::StateStore;
 
+use clap::Parser;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 use traktor::create_server;
 
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 4)]
 async fn main() -> std::io::Result<()> {
+    let config = Config::parse();
 
-    // Create state that hold traktor data arc mutex to share between tokio threads
-    let state = Arc::new(Mutex::new(TraktorState::default()));
+    match &config.command {
+        Some(Command::ApiClient { out, url }) => {
+            let url = url.clone().unwrap_or_else(|| config.api_base_url());
+            return write_api_client(&url, out.as_ref());
+        }
+        Some(Command::Replay { file, speed }) => {
+            let client = traktor::ApiClient::new(config.api_addr());
+            return traktor::replay(file, *speed, &client).await;
+        }
+        Some(Command::Simulate) => {
+            let client = traktor::ApiClient::new(config.api_addr());
+            return traktor::simulate(&client).await;
+        }
+        None => (),
+    }
+
+    // Create the store that holds the traktor data, shared between tokio threads.
+    // A restored state is shown as stale until traktor sends it again
+    let state = config.state_file.as_deref().and_then(traktor::load_state);
+    if state.is_some() {
+        println!("Restored the state from the last run");
+    }
+    let store = Arc::new(StateStore::new(state.unwrap_or_default()));
+    // Filled by the render loop and the server, served at /metrics
+    let metrics = Arc::new(Metrics::default());
 
     // start tasks in threads
-    let h1 = start_render_task(&state);
+    let h1 = start_render_task(&store, metrics.clone(), &config);
 
     h1.await;
 
-    // Keep the main task alive indefinitely
-    start_traktor_handler(state).await
+    // Keep the main task alive until the server stops, e.g. on ctrl-c
+    start_traktor_handler(store.clone(), metrics, &config).await?;
+
+    match &config.state_file {
+        Some(path) => traktor::save_state(path, &store.snapshot()),
+        None => Ok(()),
+    }
 }
 
-async fn start_render_task(state: &Arc<Mutex<TraktorState>>) {
-    let s = Arc::clone(state);
+async fn start_render_task(store: &Arc<StateStore>, metrics: Arc<Metrics>, config: &Config) {
+    // The render loop only watches for changes
+    let s = store.watch();
 
     // Create display driver
     let display = Push2Display::new().unwrap();
 
     // create graphics pipeline for display
-    let graphics =
-        render::graphics::Graphics::new(push2::DISPLAY_WIDTH as u32, push2::DISPLAY_HEIGHT as u32)
-            .await;
+    let graphics = render::graphics::Graphics::new(
+        push2::DISPLAY_WIDTH as u32,
+        push2::DISPLAY_HEIGHT as u32,
+        config.display.clone(),
+    )
+    .await;
+
+    tokio::spawn(async move { render_loop(graphics, display, s, metrics).await });
+}
 
-    tokio::spawn(async move { render_loop(graphics, display, s).await });
+async fn start_traktor_handler(
+    store: Arc<StateStore>,
+    metrics: Arc<Metrics>,
+    config: &Config,
+) -> std::io::Result<()> {
+    create_server(store, metrics, config).await
 }
 
-async fn start_traktor_handler(state: Arc<Mutex<TraktorState>>) -> std::io::Result<()> {
-    create_server(state).await
+fn write_api_client(url: &str, out: Option<&PathBuf>) -> std::io::Result<()> {
+    let js = Config::api_client_js(url);
+    match out {
+        Some(path) => {
+            std::fs::write(path, js)?;
+            println!("Wrote {} sending to {}", path.display(), url);
+        }
+        None => print!("{}", js),
+    }
+    Ok(())
 }
@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Upper bounds in seconds, a frame at 60 fps has about 16ms
+const FRAME_TIME_BUCKETS: [f64; 10] = [
+    0.002, 0.004, 0.008, 0.012, 0.016, 0.02, 0.033, 0.05, 0.1, 0.25,
+];
+const USB_TRANSFER_BUCKETS: [f64; 8] = [0.001, 0.002, 0.004, 0.008, 0.016, 0.032, 0.05, 0.1];
+
+struct Histogram {
+    bounds: &'static [f64],
+    // Not cumulative, summed up when rendered
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(i) = self.bounds.iter().position(|b| value <= *b) {
+            self.counts[i] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        header(out, name, help, "histogram");
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, self.count);
+        let _ = writeln!(out, "{}_sum {}", name, self.sum);
+        let _ = writeln!(out, "{}_count {}", name, self.count);
+    }
+}
+
+struct RenderMetrics {
+    frames: u64,
+    fps: f64,
+    frame_time: Histogram,
+    usb_transfer: Histogram,
+    usb_failures: u64,
+}
+
+#[derive(Default)]
+struct EventMetrics {
+    // Requests per endpoint and status code
+    counts: BTreeMap<(String, u16), u64>,
+    last: Option<Instant>,
+}
+
+/// Counters shared by the render loop and the server, served in the
+/// prometheus text format at /metrics
+pub struct Metrics {
+    render: Mutex<RenderMetrics>,
+    events: Mutex<EventMetrics>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            render: Mutex::new(RenderMetrics {
+                frames: 0,
+                fps: 0.0,
+                frame_time: Histogram::new(&FRAME_TIME_BUCKETS),
+                usb_transfer: Histogram::new(&USB_TRANSFER_BUCKETS),
+                usb_failures: 0,
+            }),
+            events: Mutex::new(EventMetrics::default()),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn record_frame(&self, frame_time: Duration, fps: f64) {
+        let mut render = self.render.lock().unwrap();
+        render.frames += 1;
+        render.fps = fps;
+        render.frame_time.observe(frame_time.as_secs_f64());
+    }
+
+    /// Time of the bulk writes of a sent frame, without the encoding
+    pub fn record_usb_transfer(&self, time: Duration) {
+        let mut render = self.render.lock().unwrap();
+        render.usb_transfer.observe(time.as_secs_f64());
+    }
+
+    /// Failed transfers aren't timed, they usually end in a timeout
+    pub fn record_usb_failure(&self) {
+        self.render.lock().unwrap().usb_failures += 1;
+    }
+
+    pub fn record_event(&self, endpoint: &str, status: u16) {
+        let mut events = self.events.lock().unwrap();
+        *events
+            .counts
+            .entry((endpoint.to_string(), status))
+            .or_default() += 1;
+        events.last = Some(Instant::now());
+    }
+
+    /// All metrics in the prometheus text format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        {
+            let render = self.render.lock().unwrap();
+            header(
+                &mut out,
+                "push2_frames_total",
+                "Frames sent to the display",
+                "counter",
+            );
+            let _ = writeln!(out, "push2_frames_total {}", render.frames);
+            header(&mut out, "push2_fps", "Frames in the last second", "gauge");
+            let _ = writeln!(out, "push2_fps {}", render.fps);
+            render.frame_time.render(
+                &mut out,
+                "push2_frame_time_seconds",
+                "Time to update, render and send a frame",
+            );
+            render.usb_transfer.render(
+                &mut out,
+                "push2_usb_transfer_seconds",
+                "Time of the usb bulk writes of a frame sent to the display",
+            );
+            header(
+                &mut out,
+                "push2_usb_transfer_failures_total",
+                "Frames which couldn't be sent to the display",
+                "counter",
+            );
+            let _ = writeln!(
+                out,
+                "push2_usb_transfer_failures_total {}",
+                render.usb_failures
+            );
+        }
+
+        let events = self.events.lock().unwrap();
+        header(
+            &mut out,
+            "traktor_events_total",
+            "Requests from traktor per endpoint and status",
+            "counter",
+        );
+        for ((endpoint, status), count) in &events.counts {
+            let _ = writeln!(
+                out,
+                "traktor_events_total{{endpoint=\"{}\",status=\"{}\"}} {}",
+                escape_label(endpoint),
+                status,
+                count
+            );
+        }
+        // Missing until the first event, e.g. if traktor isn't running
+        if let Some(last) = events.last {
+            header(
+                &mut out,
+                "traktor_last_event_age_seconds",
+                "Seconds since the last request from traktor",
+                "gauge",
+            );
+            let _ = writeln!(
+                out,
+                "traktor_last_event_age_seconds {}",
+                last.elapsed().as_secs_f64()
+            );
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::new(&[0.25, 0.5]);
+        histogram.observe(0.125);
+        histogram.observe(0.25);
+        histogram.observe(0.5);
+        histogram.observe(1.0);
+
+        let mut out = String::new();
+        histogram.render(&mut out, "test", "help");
+        assert_eq!(
+            out,
+            "# HELP test help\n\
+             # TYPE test histogram\n\
+             test_bucket{le=\"0.25\"} 2\n\
+             test_bucket{le=\"0.5\"} 3\n\
+             test_bucket{le=\"+Inf\"} 4\n\
+             test_sum 1.875\n\
+             test_count 4\n"
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        assert!(!metrics.render().contains("traktor_last_event_age_seconds"));
+
+        metrics.record_frame(Duration::from_millis(10), 60.0);
+        metrics.record_usb_transfer(Duration::from_millis(3));
+        metrics.record_usb_failure();
+        metrics.record_event("/fx/{fx_unit_id}", 200);
+        metrics.record_event("/fx/{fx_unit_id}", 200);
+        metrics.record_event("/fx/{fx_unit_id}", 404);
+
+        let out = metrics.render();
+        assert!(out.contains("push2_frames_total 1\n"));
+        assert!(out.contains("push2_fps 60\n"));
+        assert!(out.contains("push2_frame_time_seconds_bucket{le=\"0.012\"} 1\n"));
+        assert!(out.contains("push2_usb_transfer_seconds_count 1\n"));
+        assert!(out.contains("push2_usb_transfer_failures_total 1\n"));
+        assert!(
+            out.contains("traktor_events_total{endpoint=\"/fx/{fx_unit_id}\",status=\"200\"} 2\n")
+        );
+        assert!(
+            out.contains("traktor_events_total{endpoint=\"/fx/{fx_unit_id}\",status=\"404\"} 1\n")
+        );
+        assert!(out.contains("traktor_last_event_age_seconds "));
+    }
+}
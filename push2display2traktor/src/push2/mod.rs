@@ -1,5 +1,6 @@
 use padding::padding;
 use rusb::{Context, Device, DeviceDescriptor, DeviceHandle, UsbContext};
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use xor::xor;
 
@@ -52,8 +53,10 @@ impl Push2Display {
         Ok(Push2Display { handle })
     }
 
-    fn send_buffer(&self, buffer: &[u8]) -> Result<(), Push2DisplayError> {
-        let timeout = std::time::Duration::from_millis(50);
+    // Returns the time spent on the usb transfer
+    fn send_buffer(&self, buffer: &[u8]) -> Result<Duration, Push2DisplayError> {
+        let start = Instant::now();
+        let timeout = Duration::from_millis(50);
         // Write header to indicate frame buffer is next
         self.handle
             .write_bulk(PUSH2_BULK_EP_OUT, &HEADER, timeout)?;
@@ -68,10 +71,12 @@ impl Push2Display {
         //}
         //self.handle.write_bulk(PUSH2_BULK_EP_OUT, buffer, timeout)?;
 
-        Ok(())
+        Ok(start.elapsed())
     }
 
-    pub fn send_rgba8(&self, rgba_data: &Vec<u8>) -> Result<(), Push2DisplayError> {
+    /// Converts and sends a frame, returns the time the usb transfer took
+    /// without the conversion
+    pub fn send_rgba8(&self, rgba_data: &Vec<u8>) -> Result<Duration, Push2DisplayError> {
         let bgr565 = rgba8_to_bgr565(rgba_data);
         let encoded = encode_buffer(&bgr565);
         self.send_buffer(&encoded)
@@ -34,7 +34,8 @@ impl FPSController {
     }
 
     /// Records the end of the current frame and manages FPS and timing.
-    pub async fn end_frame(&mut self) {
+    /// Returns the time the frame took, without the sleep.
+    pub async fn end_frame(&mut self) -> Duration {
         let now = Instant::now();
         self.frame_end.push_back(now);
 
@@ -60,6 +61,7 @@ impl FPSController {
         if elapsed < self.target_duration {
             sleep(self.target_duration - elapsed).await;
         }
+        elapsed
     }
 
     /// Prints current FPS and average frame time if verbose feature is enabled.
@@ -99,7 +101,7 @@ impl FPSController {
     }
 
     /// Calculates the current FPS.
-    fn fps(&self) -> f64 {
+    pub fn fps(&self) -> f64 {
         // Count frames within the last second
         let now = Instant::now();
         let one_second_ago = now - Duration::from_secs(1);
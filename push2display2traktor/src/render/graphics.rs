@@ -1,11 +1,13 @@
-use std::sync::Arc;
-
-use tokio::sync::{oneshot, Mutex};
+use tokio::sync::{oneshot, watch};
 use wgpu::{Adapter, Buffer, Device, Extent3d, Instance, Queue, Texture, TextureView};
 
-use crate::traktor::TraktorState;
+use crate::config::DisplaySettings;
+use crate::traktor::{Changes, TraktorState, Versions};
 
-use super::pipelines::{cube::CubePipeline, knobs::KnobsIndicatorPipe, text::TextPipe, Pipeline};
+use super::pipelines::{
+    cube::CubePipeline, hotcues::HotcuePipe, knobs::KnobsIndicatorPipe, progress::ProgressPipe,
+    text::TextPipe, Pipeline,
+};
 
 pub struct Graphics {
     instance: Instance,
@@ -19,6 +21,10 @@ pub struct Graphics {
 
     // Current knob state
     knobs_pipe: KnobsIndicatorPipe,
+    // Hotcues of the focused deck
+    hotcue_pipe: HotcuePipe,
+    // Track progress and the end of track warning
+    progress_pipe: ProgressPipe,
     // Text render system for the effect names
     text_pipe: TextPipe,
 
@@ -26,10 +32,15 @@ pub struct Graphics {
     // spinning cube
     #[cfg(feature = "fancy")]
     cube_pipe: CubePipeline,
+
+    // Local copy of the traktor state, only refreshed if it changed
+    state: TraktorState,
+    // Versions the pipelines were last updated with, None before the first update
+    seen: Option<Versions>,
 }
 
 impl Graphics {
-    pub async fn new(width: u32, height: u32) -> Self {
+    pub async fn new(width: u32, height: u32, settings: DisplaySettings) -> Self {
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
             backends: wgpu::Backends::all(),
             ..Default::default()
@@ -82,11 +93,13 @@ impl Graphics {
 
         //-----------------------------------------------
         // Pipelines
-        let knobs_pipe = KnobsIndicatorPipe::new(&device, &queue, &size);
-        let text_pipe = TextPipe::new(&device, &queue, &size);
+        let knobs_pipe = KnobsIndicatorPipe::new(&device, &queue, &size, &settings);
+        let hotcue_pipe = HotcuePipe::new(&device, &queue, &size, &settings);
+        let progress_pipe = ProgressPipe::new(&device, &queue, &size, &settings);
+        let text_pipe = TextPipe::new(&device, &queue, &size, &settings);
 
         #[cfg(feature = "fancy")]
-        let cube_pipe= CubePipeline::new(&device,&queue,&size);
+        let cube_pipe= CubePipeline::new(&device,&queue,&size,&settings);
         //-----------------------------------------------
 
         Self {
@@ -99,9 +112,13 @@ impl Graphics {
             output_staging_buffer,
             size,
             knobs_pipe,
+            hotcue_pipe,
+            progress_pipe,
             text_pipe,
             #[cfg(feature = "fancy")]
-            cube_pipe
+            cube_pipe,
+            state: TraktorState::default(),
+            seen: None,
         }
     }
 
@@ -112,6 +129,8 @@ impl Graphics {
             #[cfg(feature = "fancy")]
             self.cube_pipe.prepare(&self.device, &self.queue);
             self.knobs_pipe.prepare(&self.device, &self.queue);
+            self.hotcue_pipe.prepare(&self.device, &self.queue);
+            self.progress_pipe.prepare(&self.device, &self.queue);
             self.text_pipe.prepare(&self.device, &self.queue);
 
             let mut command_encoder = self
@@ -138,6 +157,9 @@ impl Graphics {
                 #[cfg(feature = "fancy")]
                 self.cube_pipe.render(&mut render_pass);
                 self.knobs_pipe.render(&mut render_pass);
+                self.hotcue_pipe.render(&mut render_pass);
+                // Before the texts, the flash is drawn behind them
+                self.progress_pipe.render(&mut render_pass);
                 self.text_pipe.render(&mut render_pass);
             }
 
@@ -170,6 +192,8 @@ impl Graphics {
 
             self.text_pipe.render_cleanup();
             self.knobs_pipe.render_cleanup();
+            self.hotcue_pipe.render_cleanup();
+            self.progress_pipe.render_cleanup();
         }
 
         // Wait for bufferslice
@@ -189,11 +213,24 @@ impl Graphics {
         return dat;
     }
 
-    pub async fn update(&mut self, state: &Arc<Mutex<TraktorState>>) {
-        let s = state.lock().await;
+    pub fn update(&mut self, state: &mut watch::Receiver<TraktorState>) {
+        // Copy the state so the lock is released right away
+        if state.has_changed().unwrap_or(false) {
+            self.state = state.borrow_and_update().clone();
+        }
+
+        let changes = match &self.seen {
+            Some(seen) => self.state.versions().changes_since(seen),
+            None => Changes::all(),
+        };
+        self.seen = Some(*self.state.versions());
 
-        // Update pipelines
-        self.knobs_pipe.update(&s);
-        self.text_pipe.update(&s);
+        // Update pipelines, these decide themselves what to recompute
+        #[cfg(feature = "fancy")]
+        self.cube_pipe.update(&self.state, &changes);
+        self.knobs_pipe.update(&self.state, &changes);
+        self.hotcue_pipe.update(&self.state, &changes);
+        self.progress_pipe.update(&self.state, &changes);
+        self.text_pipe.update(&self.state, &changes);
     }
 }
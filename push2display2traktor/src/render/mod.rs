@@ -2,6 +2,7 @@ mod fps_controller;
 pub mod graphics;
 mod pipelines;
 pub mod storage_buffer;
+use crate::metrics::Metrics;
 use crate::push2::Push2Display;
 use crate::traktor::TraktorState;
 
@@ -9,25 +10,45 @@ use fps_controller::FPSController;
 use graphics::Graphics;
 
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::watch;
 
 pub async fn render_loop(
     mut graphics: Graphics,
     display: Push2Display,
-    state: Arc<Mutex<TraktorState>>,
+    mut state: watch::Receiver<TraktorState>,
+    metrics: Arc<Metrics>,
 ) {
     let mut fps_controller = FPSController::default();
+    let mut display_failing = false;
 
     loop {
         fps_controller.start_frame();
 
         // Update buffers via state
-        graphics.update(&state).await;
+        graphics.update(&mut state);
 
         // Render to push display
         let rgba_data = graphics.render().await;
-        display.send_rgba8(&rgba_data).unwrap();
+        let result = display.send_rgba8(&rgba_data);
+        match result {
+            Ok(usb_time) => metrics.record_usb_transfer(usb_time),
+            Err(_) => metrics.record_usb_failure(),
+        }
 
-        fps_controller.end_frame().await;
+        // Only log changes, a replugged push would flood the output otherwise
+        match result {
+            Err(e) if !display_failing => {
+                println!("Failed to send frame to push 2: {}", e);
+                display_failing = true;
+            }
+            Ok(_) if display_failing => {
+                println!("Sending frames to push 2 again");
+                display_failing = false;
+            }
+            _ => (),
+        }
+
+        let frame_time = fps_controller.end_frame().await;
+        metrics.record_frame(frame_time, fps_controller.fps());
     }
 }
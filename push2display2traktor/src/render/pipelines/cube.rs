@@ -3,8 +3,9 @@ use std::time::Instant;
 
 use super::Pipeline;
 use crate::{
+    config::DisplaySettings,
     render::storage_buffer::{StorageBuffer, StorageData},
-    traktor::TraktorState,
+    traktor::{Changes, TraktorState},
 };
 
 pub struct CubePipeline {
@@ -14,7 +15,12 @@ pub struct CubePipeline {
 }
 
 impl Pipeline<TraktorState> for CubePipeline {
-    fn new(device: &wgpu::Device, _queue: &wgpu::Queue, _size: &wgpu::Extent3d) -> Self {
+    fn new(
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _size: &wgpu::Extent3d,
+        _settings: &DisplaySettings,
+    ) -> Self {
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Shader Cube"),
             source: wgpu::ShaderSource::Wgsl(include_str!("cube.wgsl").into()),
@@ -58,7 +64,9 @@ impl Pipeline<TraktorState> for CubePipeline {
 
     fn prepare(&mut self, _device: &wgpu::Device, queue: &wgpu::Queue) {
         let duration = self.last_call.elapsed();
-        self.buffer.data.time = duration.as_secs_f32();
+        self.buffer.set(TimeStorageData {
+            time: duration.as_secs_f32(),
+        });
         self.buffer.prepare(queue);
     }
 
@@ -72,7 +80,7 @@ impl Pipeline<TraktorState> for CubePipeline {
 
     fn render_cleanup(&mut self) {}
 
-    fn update(&mut self, state: &TraktorState) {
+    fn update(&mut self, state: &TraktorState, _changes: &Changes) {
         let _ = state;
     }
 }
@@ -0,0 +1,135 @@
+use super::Pipeline;
+use crate::{
+    config::DisplaySettings,
+    render::storage_buffer::{StorageBuffer, StorageData},
+    traktor::{Changes, HotcueType, TraktorState, HOTCUES},
+};
+
+/// A row of markers, one above each pad column, showing which pads of
+/// the focused deck hold a hotcue and of which type
+pub struct HotcuePipe {
+    pipeline: wgpu::RenderPipeline,
+    buffer: StorageBuffer<HotcueStorageData>,
+}
+
+impl Pipeline<TraktorState> for HotcuePipe {
+    fn new(
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _size: &wgpu::Extent3d,
+        _settings: &DisplaySettings,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Hotcue shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("hotcues.wgsl").into()),
+        });
+
+        let buffer = StorageBuffer::new(device, HotcueStorageData::default());
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Hotcue Pipeline"),
+                bind_group_layouts: &[&buffer.bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::TextureFormat::Rgba8UnormSrgb.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self { pipeline, buffer }
+    }
+
+    fn prepare(&mut self, _device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.buffer.prepare(queue);
+    }
+
+    fn render<'pass>(&'pass self, render_pass: &mut wgpu::RenderPass<'pass>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.buffer.bind_group, &[]);
+
+        // One rectangle per pad
+        render_pass.draw(0..6, 0..HOTCUES as u32);
+    }
+
+    fn render_cleanup(&mut self) {}
+
+    fn update(&mut self, state: &TraktorState, changes: &Changes) {
+        // The focus falls back to the master deck
+        if changes.decks || changes.master_clock {
+            self.buffer.set(HotcueStorageData::from(state));
+        }
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                             My storage data                                */
+/* -------------------------------------------------------------------------- */
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct HotcueStorageData {
+    // 0 for an empty pad, otherwise the traktor type + 1
+    types: [u32; HOTCUES as usize], // 8*4 = 32
+}
+
+impl StorageData for HotcueStorageData {
+    fn default() -> Self {
+        Self {
+            types: [0; HOTCUES as usize],
+        }
+    }
+
+    fn create_bind_group_layout(&self, device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("Hotcue bind group layout"),
+        })
+    }
+}
+
+impl From<&TraktorState> for HotcueStorageData {
+    fn from(state: &TraktorState) -> Self {
+        let mut types = [0; HOTCUES as usize];
+        if let Some(deck) = state.focused_deck() {
+            for (i, hotcue) in deck.hotcues.iter().enumerate() {
+                types[i] = match hotcue.as_ref().map(|h| h.r#type) {
+                    None => 0,
+                    Some(HotcueType::Cue) => 1,
+                    Some(HotcueType::FadeIn) => 2,
+                    Some(HotcueType::FadeOut) => 3,
+                    Some(HotcueType::Load) => 4,
+                    Some(HotcueType::Grid) => 5,
+                    Some(HotcueType::Loop) => 6,
+                };
+            }
+        }
+        Self { types }
+    }
+}
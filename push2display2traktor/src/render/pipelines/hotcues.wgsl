@@ -0,0 +1,53 @@
+@group(0)
+@binding(0)
+var<storage,read> hotcues: Hotcues;
+struct Hotcues {
+    // 0 for an empty pad, otherwise the traktor type + 1
+    types: array<u32,8>
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) @interpolate(flat) hotcue_type: u32,
+};
+
+// Between the deck texts and the button labels, 6px high (y 121-127)
+const TOP: f32 = -0.5125;
+const BOTTOM: f32 = -0.5875;
+// Gap to the neighbouring pads in clip space, 10px
+const GAP: f32 = 0.0208;
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32, @builtin(instance_index) in_instance_index: u32) -> VertexOutput {
+    // Each pad gets an eighth of the width
+    var left: f32 = -1.0 + f32(in_instance_index) * 0.25 + GAP / 2.0;
+    var right: f32 = left + 0.25 - GAP;
+
+    var vertices: array<vec4<f32>, 6> = array<vec4<f32>, 6>(
+        vec4<f32>(left, BOTTOM, 0.0, 1.0),
+        vec4<f32>(left, TOP, 0.0, 1.0),
+        vec4<f32>(right, TOP, 0.0, 1.0),
+
+        vec4<f32>(left, BOTTOM, 0.0, 1.0),
+        vec4<f32>(right, TOP, 0.0, 1.0),
+        vec4<f32>(right, BOTTOM, 0.0, 1.0)
+    );
+
+    var out: VertexOutput;
+    out.position = vertices[in_vertex_index % 6u];
+    out.hotcue_type = hotcues.types[in_instance_index];
+    return out;
+}
+
+// Roughly the colors traktor uses for the cue types
+@fragment
+fn fs_main(@location(0) @interpolate(flat) hotcue_type: u32) -> @location(0) vec4<f32> {
+    switch hotcue_type {
+        case 1u: { return vec4<f32>(0.1, 0.4, 1.0, 1.0); } // Cue, blue
+        case 2u, 3u: { return vec4<f32>(1.0, 0.4, 0.0, 1.0); } // Fade in/out, orange
+        case 4u: { return vec4<f32>(1.0, 0.85, 0.0, 1.0); } // Load, yellow
+        case 5u: { return vec4<f32>(1.0, 1.0, 1.0, 1.0); } // Grid, white
+        case 6u: { return vec4<f32>(0.1, 0.8, 0.2, 1.0); } // Loop, green
+        default: { return vec4<f32>(0.12, 0.12, 0.12, 1.0); } // Empty pad
+    }
+}
@@ -1,7 +1,8 @@
 use super::Pipeline;
 use crate::{
+    config::DisplaySettings,
     render::storage_buffer::{StorageBuffer, StorageData},
-    traktor::TraktorState,
+    traktor::{Changes, TraktorState},
 };
 
 pub struct KnobsIndicatorPipe {
@@ -10,7 +11,12 @@ pub struct KnobsIndicatorPipe {
 }
 
 impl Pipeline<TraktorState> for KnobsIndicatorPipe {
-    fn new(device: &wgpu::Device, _queue: &wgpu::Queue, _size: &wgpu::Extent3d) -> Self {
+    fn new(
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _size: &wgpu::Extent3d,
+        _settings: &DisplaySettings,
+    ) -> Self {
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Shader"),
             source: wgpu::ShaderSource::Wgsl(include_str!("knobs.wgsl").into()),
@@ -67,8 +73,10 @@ impl Pipeline<TraktorState> for KnobsIndicatorPipe {
         return;
     }
 
-    fn update(&mut self, state: &TraktorState) {
-        self.buffer.data = KnobStorageData::from(state);
+    fn update(&mut self, state: &TraktorState, changes: &Changes) {
+        if changes.fx_units {
+            self.buffer.set(KnobStorageData::from(state));
+        }
     }
 }
 
@@ -1,6 +1,24 @@
+pub mod hotcues;
 pub mod knobs;
+pub mod progress;
 pub mod text;
 pub mod cube;
+
+use crate::config::DisplaySettings;
+use crate::traktor::{Changes, DeckID};
+
+/// Width of a deck's column, half of the display
+pub const COLUMN_WIDTH: u32 = 480;
+
+/// Column a deck is drawn in, shared by all per deck pipelines.
+/// Only one deck per column is shown, see `TraktorState::is_deck_shown`
+pub fn deck_column(deck_id: &DeckID) -> u32 {
+    match deck_id {
+        DeckID::A | DeckID::C => 0,
+        DeckID::B | DeckID::D => 1,
+    }
+}
+
 /// A trait that defines the required methods for a rendering pipeline.
 /// This more or less follows the middleware pattern
 /// see https://github.com/gfx-rs/wgpu/wiki/Encapsulating-Graphics-Work
@@ -12,11 +30,17 @@ pub trait Pipeline<State> {
     /// * `device` - A reference to the GPU device.
     /// * `queue` - A reference to the GPU queue.
     /// * `size` - A reference to the dimensions of the rendering target.
+    /// * `settings` - What the display shows, as configured on the command line.
     ///
     /// # Returns
     ///
     /// Returns a new instance of the implementing render pipeline.
-    fn new(device: &wgpu::Device, queue: &wgpu::Queue, size: &wgpu::Extent3d) -> Self;
+    fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: &wgpu::Extent3d,
+        settings: &DisplaySettings,
+    ) -> Self;
 
     /// Prepares the render pipeline for rendering.
     ///
@@ -48,15 +72,27 @@ pub trait Pipeline<State> {
 
     /// Updates the render pipeline based on the current state.
     ///
-    /// This asynchronous method is called to update the texts or other dynamic elements
-    /// in the render pipeline based on the current state.
+    /// This method is called every frame to update the texts or other dynamic elements
+    /// in the render pipeline based on the current state. Work should only be redone
+    /// for the sections of the state which changed since the last call.
     ///
     /// # Arguments
     ///
     /// * `state` - A reference to the current state.
-    ///
-    /// # Returns
-    ///
-    /// Returns a future that resolves when the update is complete.
-    fn update(&mut self, state: &State);
+    /// * `changes` - The sections of the state which changed since the last call.
+    fn update(&mut self, state: &State, changes: &Changes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::push2::DISPLAY_WIDTH;
+
+    #[test]
+    fn test_deck_columns_on_display() {
+        for deck_id in DeckID::iter() {
+            let right = (deck_column(deck_id) + 1) * COLUMN_WIDTH;
+            assert!(right <= DISPLAY_WIDTH as u32, "{:?} is off screen", deck_id);
+        }
+    }
 }
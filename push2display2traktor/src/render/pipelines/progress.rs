@@ -0,0 +1,162 @@
+use std::time::Instant;
+
+use super::{deck_column, Pipeline};
+use crate::{
+    config::DisplaySettings,
+    render::storage_buffer::{StorageBuffer, StorageData},
+    traktor::{Changes, TraktorState},
+};
+
+// One bar per deck, in the deck's column like its texts
+// but only for the decks which are shown
+const DECKS: usize = 4;
+
+/// A progress bar above each shown deck's texts, which turns red near the
+/// end of the track while the deck's half of the display flashes
+pub struct ProgressPipe {
+    settings: DisplaySettings,
+    pipeline: wgpu::RenderPipeline,
+    buffer: StorageBuffer<ProgressStorageData>,
+    // Flashing follows the wall clock rather than the track
+    start: Instant,
+}
+
+impl Pipeline<TraktorState> for ProgressPipe {
+    fn new(
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _size: &wgpu::Extent3d,
+        settings: &DisplaySettings,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Progress shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("progress.wgsl").into()),
+        });
+
+        let buffer = StorageBuffer::new(device, ProgressStorageData::default());
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Progress Pipeline"),
+                bind_group_layouts: &[&buffer.bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::TextureFormat::Rgba8UnormSrgb.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            settings: settings.clone(),
+            pipeline,
+            buffer,
+            start: Instant::now(),
+        }
+    }
+
+    fn prepare(&mut self, _device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.buffer.prepare(queue);
+    }
+
+    fn render<'pass>(&'pass self, render_pass: &mut wgpu::RenderPass<'pass>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.buffer.bind_group, &[]);
+
+        // Flash, bar background and bar for each deck
+        render_pass.draw(0..6, 0..3 * DECKS as u32);
+    }
+
+    fn render_cleanup(&mut self) {}
+
+    fn update(&mut self, state: &TraktorState, changes: &Changes) {
+        // The position keeps moving while a deck is playing
+        let is_playing = state.iter_all_decks().any(|d| d.playback.is_playing);
+        // The shown decks follow the focus, or the master deck without one
+        if changes.decks || changes.master_clock || is_playing {
+            let now = Instant::now();
+            // Twice a second, on for the first half
+            let flash_on = self.start.elapsed().as_millis() % 500 < 250;
+            let mut data = ProgressStorageData::default();
+
+            for (i, deck) in state.iter_all_decks().enumerate() {
+                let (Some(c), Some(remaining)) = (&deck.content, deck.remaining_at(now)) else {
+                    continue;
+                };
+                if !state.is_deck_shown(&deck.id) {
+                    continue;
+                }
+                data.column[i] = deck_column(&deck.id) as i32;
+                data.progress[i] = (deck.position_at(now) / c.length) as f32;
+
+                let end_warning = self.settings.end_warning as f64;
+                if remaining < end_warning {
+                    // Only playing decks flash, like in traktor
+                    data.warning[i] = if deck.playback.is_playing && flash_on {
+                        2
+                    } else {
+                        1
+                    };
+                }
+            }
+            self.buffer.set(data);
+        }
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                             My storage data                                */
+/* -------------------------------------------------------------------------- */
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ProgressStorageData {
+    // Played part of the track, 0-1
+    progress: [f32; DECKS], // 4*4 = 16
+    // 0 for none, 1 near the end, 2 near the end and flashing
+    warning: [u32; DECKS], // 4*4 = 16
+    // Column of the deck, -1 hides decks without a track or not shown
+    column: [i32; DECKS], // 4*4 = 16
+}
+
+impl StorageData for ProgressStorageData {
+    fn default() -> Self {
+        Self {
+            progress: [0.0; DECKS],
+            warning: [0; DECKS],
+            column: [-1; DECKS],
+        }
+    }
+
+    fn create_bind_group_layout(&self, device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("Progress bind group layout"),
+        })
+    }
+}
@@ -0,0 +1,89 @@
+@group(0)
+@binding(0)
+var<storage,read> progress: Progress;
+struct Progress {
+    // Played part of the track, 0-1, per deck
+    progress: array<f32,4>,
+    // 0 for none, 1 near the end, 2 near the end and flashing
+    warning: array<u32,4>,
+    // Column of 480px the deck is drawn in, -1 if it has no track or is hidden
+    column: array<i32,4>
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) @interpolate(flat) kind: u32,
+    @location(1) @interpolate(flat) warning: u32,
+};
+
+// Between the tempo line and the deck texts, 2px high (y 58-60)
+const BAR_TOP: f32 = 0.275;
+const BAR_BOTTOM: f32 = 0.25;
+// From the tempo line to the end of the deck texts (y 36-120)
+const REGION_TOP: f32 = 0.55;
+const REGION_BOTTOM: f32 = -0.5;
+// Padding to the display edge and the middle, 5px
+const PADDING: f32 = 0.0104;
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32, @builtin(instance_index) in_instance_index: u32) -> VertexOutput {
+    // Instances are the flash, the bar background and the bar, per deck
+    let kind = in_instance_index / 4u;
+    let deck = in_instance_index % 4u;
+    let warning = progress.warning[deck];
+    let column = progress.column[deck];
+
+    // Each column is half of the width
+    var left: f32 = -1.0 + f32(column) + PADDING;
+    var right: f32 = left + 1.0 - 2.0 * PADDING;
+    // Nothing to draw without a track
+    if column < 0 {
+        right = left;
+    }
+    var top: f32 = BAR_TOP;
+    var bottom: f32 = BAR_BOTTOM;
+    switch kind {
+        case 0u: {
+            top = REGION_TOP;
+            bottom = REGION_BOTTOM;
+            // Nothing to draw outside of a flash
+            if warning != 2u {
+                right = left;
+            }
+        }
+        case 2u: {
+            right = left + (right - left) * clamp(progress.progress[deck], 0.0, 1.0);
+        }
+        default: {}
+    }
+
+    var vertices: array<vec4<f32>, 6> = array<vec4<f32>, 6>(
+        vec4<f32>(left, bottom, 0.0, 1.0),
+        vec4<f32>(left, top, 0.0, 1.0),
+        vec4<f32>(right, top, 0.0, 1.0),
+
+        vec4<f32>(left, bottom, 0.0, 1.0),
+        vec4<f32>(right, top, 0.0, 1.0),
+        vec4<f32>(right, bottom, 0.0, 1.0)
+    );
+
+    var out: VertexOutput;
+    out.position = vertices[in_vertex_index % 6u];
+    out.kind = kind;
+    out.warning = warning;
+    return out;
+}
+
+@fragment
+fn fs_main(@location(0) @interpolate(flat) kind: u32, @location(1) @interpolate(flat) warning: u32) -> @location(0) vec4<f32> {
+    switch kind {
+        case 0u: { return vec4<f32>(0.35, 0.0, 0.0, 1.0); } // Flash, dark red behind the texts
+        case 1u: { return vec4<f32>(0.12, 0.12, 0.12, 1.0); } // Bar background
+        default: {
+            if warning > 0u {
+                return vec4<f32>(1.0, 0.15, 0.1, 1.0); // Near the end, red
+            }
+            return vec4<f32>(0.6, 0.6, 0.6, 1.0);
+        }
+    }
+}
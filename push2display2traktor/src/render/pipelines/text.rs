@@ -2,24 +2,36 @@ use glyphon::{
     cosmic_text::Align, Attrs, Buffer, Cache, Color, Family, FontSystem, Metrics, Resolution,
     Shaping, SwashCache, TextArea, TextAtlas, TextBounds, TextRenderer, Viewport,
 };
+use std::time::Instant;
 use wgpu::{Device, Extent3d, Queue, RenderPass};
 
-use super::Pipeline;
-use crate::traktor::{DeckID, TraktorState};
+use super::{deck_column, Pipeline, COLUMN_WIDTH};
+use crate::config::DisplaySettings;
+use crate::traktor::{
+    beats::{BeatPosition, BEATS_PER_BAR},
+    keys::Compatibility,
+    tempo::DeckTempo,
+    Changes, Deck, DeckID, TraktorState, PROTOCOL_VERSION,
+};
 
 pub struct TextPipe {
     pub swash_cache: SwashCache,
     pub viewport: Viewport,
     pub atlas: TextAtlas,
     pub renderer: TextRenderer,
+    settings: DisplaySettings,
     knob_texts: Vec<TextStorageData>,
     content_texts: Vec<TextStorageData>,
+    // A line above each deck's content
+    tempo_texts: Vec<TextStorageData>,
     other_texts: Vec<TextStorageData>,
+    // Shown instead of the decks if traktor speaks another protocol
+    warning_text: Option<TextStorageData>,
     font_system: FontSystem,
 }
 
 impl Pipeline<TraktorState> for TextPipe {
-    fn new(device: &Device, queue: &Queue, size: &Extent3d) -> Self {
+    fn new(device: &Device, queue: &Queue, size: &Extent3d, settings: &DisplaySettings) -> Self {
         let swash_cache = SwashCache::new();
         let cache = Cache::new(device);
         let mut viewport = Viewport::new(device, &cache);
@@ -120,9 +132,12 @@ impl Pipeline<TraktorState> for TextPipe {
             viewport,
             atlas,
             renderer,
+            settings: settings.clone(),
             knob_texts: vec![],
             content_texts: vec![],
+            tempo_texts: vec![],
             other_texts,
+            warning_text: None,
             font_system,
         }
     }
@@ -133,7 +148,15 @@ impl Pipeline<TraktorState> for TextPipe {
         }
 
         let knobs = self.knob_texts.iter().map(|x| x.to_text_area());
-        let content = self.content_texts.iter().map(|x| x.to_text_area());
+        let content = match &self.warning_text {
+            Some(warning) => vec![warning.to_text_area()],
+            None => self
+                .content_texts
+                .iter()
+                .chain(&self.tempo_texts)
+                .map(|x| x.to_text_area())
+                .collect(),
+        };
         let other = self.other_texts.iter().map(|x| x.to_text_area());
 
         // Combine vectors
@@ -169,9 +192,27 @@ impl Pipeline<TraktorState> for TextPipe {
     }
 
     // Updates the texts from the traktor state
-    fn update(&mut self, state: &TraktorState) {
+    fn update(&mut self, state: &TraktorState, changes: &Changes) {
+        if changes.fx_units {
+            self.update_knob_texts(state);
+        }
+        if changes.client {
+            self.update_warning_text(state);
+        }
+
+        // The elapsed time keeps moving while a deck is playing
+        let is_playing = state.iter_all_decks().any(|d| d.playback.is_playing);
+        if changes.decks || changes.channels || changes.master_clock || is_playing {
+            self.update_content_texts(state);
+        }
+    }
+}
+
+impl TextPipe {
+    fn update_knob_texts(&mut self, state: &TraktorState) {
         // Update the 16 knob texts
-        for (i, fx_name) in state.iter_knob_fx_names().enumerate() {
+        let stale = state.iter_knob_stale();
+        for (i, (fx_name, stale)) in state.iter_knob_fx_names().zip(stale).enumerate() {
             if self.knob_texts.len() < i + 1 {
                 self.knob_texts.push(TextStorageData::new_knob(
                     fx_name.clone(),
@@ -184,11 +225,99 @@ impl Pipeline<TraktorState> for TextPipe {
                     self.knob_texts[i].update_buffer(&mut self.font_system);
                 }
             }
+            self.knob_texts[i].color = if stale { STALE_COLOR } else { ON_AIR_COLOR };
         }
-        // Update the content for each
+    }
+
+    fn update_warning_text(&mut self, state: &TraktorState) {
+        let client = state.client();
+        self.warning_text = match client.protocol_version {
+            Some(version) if !client.is_compatible() => {
+                let text = format!(
+                    "TRAKTOR SENDS PROTOCOL {}, EXPECTED {}\nCOPY THE D2 FOLDER OF THIS RELEASE",
+                    version, PROTOCOL_VERSION
+                );
+                let mut warning = TextStorageData::new(
+                    text,
+                    &mut self.font_system,
+                    5.0,
+                    60.0,
+                    Some(960.0 - 5.0),
+                    None,
+                    None,
+                );
+                warning.color = WARNING_COLOR;
+                Some(warning)
+            }
+            _ => None,
+        };
+    }
+
+    fn update_content_texts(&mut self, state: &TraktorState) {
+        // Update the content for each, positions are sampled once per frame
+        let now = Instant::now();
+        let notation = self.settings.key_notation;
+        // Each shown deck is compared to the one in the other column
+        let other_key = |deck: &Deck| {
+            state
+                .iter_all_decks()
+                .find(|d| d.id != deck.id && state.is_deck_shown(&d.id))?
+                .content
+                .as_ref()?
+                .musical_key()
+        };
         for (i, deck) in state.iter_all_decks().enumerate() {
-            let text: String = if let Some(c) = &deck.content {
-                format!("{}\n{}\n{}", c.resulting_key, c.artist, c.title,)
+            // The other deck of each column is hidden
+            let content = deck
+                .content
+                .as_ref()
+                .filter(|_| state.is_deck_shown(&deck.id));
+            let text: String = if let Some(c) = content {
+                // The tempo is on the line above
+                let master = if state.is_master_deck(&deck.id) {
+                    " MASTER".to_string()
+                } else {
+                    "".to_string()
+                };
+                let position = deck.position_at(now);
+                let remaining = match deck.remaining_at(now) {
+                    Some(remaining) => format!(" -{}", format_time(remaining.ceil())),
+                    None => "".to_string(),
+                };
+                let beats = match BeatPosition::of_track(c, position) {
+                    Some(beat) => format!(
+                        " {}.{} -{}",
+                        beat.bar(),
+                        beat.beat_in_bar(),
+                        format_countdown(beat.beats_to_phrase(self.settings.phrase_bars))
+                    ),
+                    None => "".to_string(),
+                };
+                // Unknown formats are shown as traktor sent them
+                let key = match (c.musical_key(), other_key(deck)) {
+                    (Some(key), Some(other)) => format!(
+                        "{} {}",
+                        key.format(notation),
+                        format_compatibility(key.compatibility(&other))
+                    ),
+                    (Some(key), None) => key.format(notation),
+                    (None, _) => c.resulting_key.clone(),
+                };
+                let looping = match deck.r#loop.size {
+                    Some(size) if deck.r#loop.is_active => format!(" LOOP {}", format_beats(size)),
+                    _ => "".to_string(),
+                };
+                format!(
+                    "{}{}{} {}{}{}\n{}\n{}",
+                    format_time(position),
+                    remaining,
+                    beats,
+                    key,
+                    looping,
+                    master,
+                    c.artist,
+                    c.title,
+                )
             } else {
                 "".to_string()
             };
@@ -206,12 +335,110 @@ impl Pipeline<TraktorState> for TextPipe {
                    self.content_texts[i].update_buffer(&mut self.font_system);
                 }
             }
-     
+
+            // Dim decks which are not audible, restored ones until confirmed
+            let color = if deck.stale {
+                STALE_COLOR
+            } else if state.is_deck_on_air(&deck.id) {
+                ON_AIR_COLOR
+            } else {
+                OFF_AIR_COLOR
+            };
+            self.content_texts[i].color = color;
+
+            self.update_tempo_text(i, state, deck, color);
+        }
+
+    }
+
+    fn update_tempo_text(&mut self, i: usize, state: &TraktorState, deck: &Deck, color: Color) {
+        let tempo = DeckTempo::of(deck).filter(|_| state.is_deck_shown(&deck.id));
+        // The master deck sets the tempo, no need to compare it
+        let difference = tempo
+            .filter(|_| !state.is_master_deck(&deck.id))
+            .and_then(|t| t.difference_to(state.master_clock().bpm));
+
+        let text = match (tempo, difference) {
+            (Some(t), Some(d)) => format!(
+                "{:.2} BPM {:+.1}% / {:+.2} ({:+.1}%)",
+                t.bpm, t.pitch, d.bpm, d.percent
+            ),
+            (Some(t), None) => format!("{:.2} BPM {:+.1}%", t.bpm, t.pitch),
+            _ => "".to_string(),
+        };
+
+        if self.tempo_texts.len() < i + 1 {
+            self.tempo_texts.push(TextStorageData::new_tempo(
+                text,
+                &mut self.font_system,
+                deck.id.clone(),
+            ));
+        } else if self.tempo_texts[i].text != text {
+            self.tempo_texts[i].text = text;
+            self.tempo_texts[i].update_buffer(&mut self.font_system);
         }
 
+        // Out of reach of the pitch fader
+        self.tempo_texts[i].color = match difference {
+            Some(d) if d.is_out_of_range(self.settings.pitch_range) => WARNING_COLOR,
+            _ => color,
+        };
+    }
+}
+
+/// Formats seconds as mm:ss
+fn format_time(seconds: f64) -> String {
+    let seconds = seconds.max(0.0) as u64;
+    format!("{:02}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Formats the beats left as bars.beats, e.g. 6 beats are 1.2
+fn format_countdown(beats: i64) -> String {
+    let beats = beats - 1;
+    format!("{}.{}", beats / BEATS_PER_BAR, beats % BEATS_PER_BAR + 1)
+}
+
+/// Formats a loop size, fractions as 1/n
+fn format_beats(beats: f64) -> String {
+    if beats < 1.0 {
+        format!("1/{}", (1.0 / beats).round())
+    } else {
+        format!("{}", beats.round())
     }
 }
 
+/// Short tag for how a key mixes with the other shown deck
+fn format_compatibility(compatibility: Option<Compatibility>) -> &'static str {
+    match compatibility {
+        Some(Compatibility::Same) => "=",
+        Some(Compatibility::Adjacent(1)) => "+1",
+        Some(Compatibility::Adjacent(_)) => "-1",
+        Some(Compatibility::Relative) => "REL",
+        Some(Compatibility::EnergyBoost) => "+2",
+        None => "!",
+    }
+}
+
+const ON_AIR_COLOR: Color = Color::rgb(255, 255, 255);
+const OFF_AIR_COLOR: Color = Color::rgb(110, 110, 110);
+const STALE_COLOR: Color = Color::rgb(150, 110, 40);
+const WARNING_COLOR: Color = Color::rgb(255, 60, 40);
+
+/// Left, right and alignment of a deck's half of the display
+fn deck_bounds(deck_id: DeckID) -> (f32, f32, Align) {
+    let id = deck_column(&deck_id);
+    // Texts point to the outer edges
+    let align = if id == 0 { Align::Left } else { Align::Right };
+
+    //960/2 -5*2 = 470
+    let width = COLUMN_WIDTH as f32;
+    (
+        5.0 + width * id as f32,
+        width * (id + 1) as f32 - 5.0,
+        align,
+    )
+}
+
 /// A text helper to render a text on the screen
 struct TextStorageData {
     text: String,
@@ -221,6 +448,7 @@ struct TextStorageData {
     right: f32,
     bottom: f32,
     pub alignment: Align,
+    pub color: Color,
 }
 
 impl TextStorageData {
@@ -239,26 +467,25 @@ impl TextStorageData {
         );
     }
     fn new_content(text: String, font_system: &mut FontSystem, deck_id: DeckID) -> Self {
-        let (id, align) = match deck_id {
-            DeckID::A => (0, Align::Left),
-            DeckID::B => (1, Align::Right),
-            DeckID::C => (0, Align::Left),
-            DeckID::D => (3, Align::Right),
-        };
-
-        //960/2 -5*2 = 470
+        let (left, right, align) = deck_bounds(deck_id);
         let c = Self::new(
             text,
             font_system,
-            5.0 + 480.0 * id as f32,
+            left,
             60.0,
-            Some(480.0 * (id + 1) as f32 - 5.0),
+            Some(right),
             None, //Some(60.0+20.0*3.0), //lineheight*3
             Some(align),
         );
         c
     }
 
+    fn new_tempo(text: String, font_system: &mut FontSystem, deck_id: DeckID) -> Self {
+        // One line between the knob names and the content
+        let (left, right, align) = deck_bounds(deck_id);
+        Self::new(text, font_system, left, 38.0, Some(right), Some(58.0), Some(align))
+    }
+
     fn new(
         text: String,
         mut font_system: &mut FontSystem,
@@ -288,6 +515,7 @@ impl TextStorageData {
             right,
             bottom,
             alignment: alignment.unwrap_or(Align::Center),
+            color: ON_AIR_COLOR,
         };
         s.update_buffer(font_system);
         s
@@ -321,7 +549,7 @@ impl TextStorageData {
                 right: self.right as i32,
                 bottom: self.bottom as i32,
             },
-            default_color: Color::rgb(255, 255, 255),
+            default_color: self.color,
         }
     }
 }
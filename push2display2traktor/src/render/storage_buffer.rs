@@ -6,7 +6,9 @@ pub struct StorageBuffer<Data: StorageData> {
     buffer: Buffer,
     pub bind_group: BindGroup,
     pub bind_group_layout: BindGroupLayout,
-    pub data: Data,
+    data: Data,
+    // Data changed since it was last written to the GPU
+    dirty: bool,
 }
 
 /// A trait that needs to be implemented by any data type used as uniform data in `StorageBuffer`.
@@ -55,18 +57,28 @@ impl<Data: StorageData> StorageBuffer<Data> {
             bind_group,
             bind_group_layout,
             data: uniform,
+            dirty: false,
         }
     }
 
+    /// Replaces the uniform data, it is written to the GPU on the next `prepare`.
+    pub fn set(&mut self, data: Data) {
+        self.data = data;
+        self.dirty = true;
+    }
+
     /// Updates the buffer with new uniform data.
     ///
     /// # Arguments
     /// - `queue`: The `wgpu::Queue` to use for writing the new data to the buffer.
     ///
-    /// This function updates the internal uniform data and writes it to the GPU buffer.
+    /// This function writes the internal uniform data to the GPU buffer if it changed.
     pub fn prepare(&mut self, queue: &Queue) {
-        // TODO i dont think we need to do this every update
+        if !self.dirty {
+            return;
+        }
         queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.data]));
+        self.dirty = false;
     }
 
     /// Gets a reference to the bind group for this uniform buffer.
@@ -0,0 +1,80 @@
+use crate::traktor::DeckContent;
+
+pub const BEATS_PER_BAR: i64 = 4;
+
+/// Position in the beatgrid of a track, derived from the grid offset
+/// and the original tempo. Counting starts at the first beat of the
+/// grid, so bar 1 beat 1 is the grid offset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BeatPosition {
+    // Beats since the first beat of the grid, 0 on the first one
+    beat: i64,
+}
+
+impl BeatPosition {
+    /// None before the first beat or without a usable grid
+    pub fn at(position: f64, grid_offset: f64, bpm: f64) -> Option<Self> {
+        if bpm <= 0.0 || !bpm.is_finite() || position < grid_offset {
+            return None;
+        }
+        // The position is in track time, so tempo changes don't matter
+        let beat = ((position - grid_offset) * bpm / 60.0).floor() as i64;
+        Some(Self { beat })
+    }
+
+    /// Position in the track's own grid, None if it has no tempo
+    pub fn of_track(content: &DeckContent, position: f64) -> Option<Self> {
+        Self::at(position, content.grid_offset, content.bpm)
+    }
+
+    /// Bar number starting at 1
+    pub fn bar(&self) -> i64 {
+        self.beat / BEATS_PER_BAR + 1
+    }
+
+    /// Beat within the bar, 1-4
+    pub fn beat_in_bar(&self) -> i64 {
+        self.beat % BEATS_PER_BAR + 1
+    }
+
+    /// Beats until the next phrase starts, a phrase being `phrase_bars`
+    /// bars long. Counts down to 1 on the last beat of a phrase.
+    pub fn beats_to_phrase(&self, phrase_bars: u32) -> i64 {
+        let phrase_beats = phrase_bars.max(1) as i64 * BEATS_PER_BAR;
+        phrase_beats - self.beat % phrase_beats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_bar_and_beat() {
+        // 120 bpm, a beat every half second from 0.25
+        let at = |position| BeatPosition::at(position, 0.25, 120.0);
+        assert_eq!(at(0.0), None);
+
+        let first = at(0.25).unwrap();
+        assert_eq!((first.bar(), first.beat_in_bar()), (1, 1));
+        let fourth = at(1.99).unwrap();
+        assert_eq!((fourth.bar(), fourth.beat_in_bar()), (1, 4));
+        let next_bar = at(2.25).unwrap();
+        assert_eq!((next_bar.bar(), next_bar.beat_in_bar()), (2, 1));
+        let later = at(0.25 + 33.0 * 0.5).unwrap();
+        assert_eq!((later.bar(), later.beat_in_bar()), (9, 2));
+
+        assert_eq!(BeatPosition::at(10.0, 0.0, 0.0), None);
+    }
+
+    #[test]
+    fn test_beats_to_phrase() {
+        let beat = |beat| BeatPosition { beat };
+        assert_eq!(beat(0).beats_to_phrase(16), 64);
+        assert_eq!(beat(63).beats_to_phrase(16), 1);
+        assert_eq!(beat(64).beats_to_phrase(16), 64);
+        assert_eq!(beat(64).beats_to_phrase(32), 64);
+        assert_eq!(beat(100).beats_to_phrase(32), 28);
+    }
+}
@@ -0,0 +1,78 @@
+use std::{io, net::SocketAddr};
+
+use crate::traktor::{PROTOCOL_HEADER, PROTOCOL_VERSION};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// Posts json to the api the same way ApiClient.js does from within traktor.
+///
+/// Only used to replay or simulate traktor, so one connection per request
+/// is fast enough and we don't need a full http client.
+pub struct ApiClient {
+    addr: SocketAddr,
+}
+
+impl ApiClient {
+    pub fn new(addr: SocketAddr) -> Self {
+        Self { addr }
+    }
+
+    /// Sends the body to the endpoint and returns the status code
+    pub async fn post(&self, path: &str, body: &str) -> io::Result<u16> {
+        self.post_as(path, body, Some(&PROTOCOL_VERSION.to_string()))
+            .await
+    }
+
+    /// Sends the body unchanged with the given protocol header, or none
+    /// like a client from before the handshake
+    pub async fn post_as(&self, path: &str, body: &str, protocol: Option<&str>) -> io::Result<u16> {
+        let mut stream = TcpStream::connect(self.addr).await?;
+        let protocol = protocol
+            .map(|p| format!("{}: {}\r\n", PROTOCOL_HEADER, p))
+            .unwrap_or_default();
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+            path,
+            self.addr,
+            body.len(),
+            protocol,
+            body
+        );
+        stream.write_all(request.as_bytes()).await?;
+
+        let mut response = vec![];
+        stream.read_to_end(&mut response).await?;
+        parse_status(&response)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid http response"))
+    }
+}
+
+// Status code from the first line, e.g. "HTTP/1.1 200 OK"
+fn parse_status(response: &[u8]) -> Option<u16> {
+    let line = response.split(|b| *b == b'\n').next()?;
+    std::str::from_utf8(line)
+        .ok()?
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_parse_status() {
+        assert_eq!(
+            parse_status(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n"),
+            Some(200)
+        );
+        assert_eq!(parse_status(b"HTTP/1.1 404 Not Found\r\n"), Some(404));
+        assert_eq!(parse_status(b""), None);
+        assert_eq!(parse_status(b"garbage"), None);
+    }
+}
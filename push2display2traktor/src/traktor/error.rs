@@ -0,0 +1,86 @@
+use actix_web::{
+    error::{JsonPayloadError, PathError},
+    http::StatusCode,
+    HttpRequest, HttpResponse, ResponseError,
+};
+use serde::Serialize;
+use thiserror::Error;
+
+/// Rejected requests from the traktor api, these are answered with a
+/// json body instead of panicking the worker
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("Unknown fx unit {0}, expected 1-{max}", max = super::FX_UNITS)]
+    FxUnit(usize),
+
+    #[error("Unknown knob in '{0}', expected 1-{max}", max = super::KNOBS_PER_FX_UNIT - 1)]
+    Knob(String),
+
+    #[error("Unknown deck '{0}', expected A-D")]
+    Deck(String),
+
+    #[error("Unknown channel {0}, expected 1-{max}", max = super::CHANNELS)]
+    Channel(u8),
+
+    #[error("Unknown hotcue {0}, expected 1-{max}", max = super::HOTCUES)]
+    Hotcue(u8),
+
+    #[error("Unknown hotcue type {0}, expected 0-5")]
+    HotcueType(u8),
+
+    #[error("Invalid path: {0}")]
+    Path(String),
+
+    #[error("Invalid body: {0}")]
+    Body(String),
+}
+
+/// Answers extractor errors for the body like our own rejections
+pub fn json_error(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    ApiError::Body(err.to_string()).into()
+}
+
+/// Answers extractor errors for the path like our own rejections
+pub fn path_error(err: PathError, _req: &HttpRequest) -> actix_web::Error {
+    ApiError::Path(err.to_string()).into()
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+impl ResponseError for ApiError {
+    // The route exists but the client sent something it can't mean, a
+    // broken ApiClient.js. Only unknown routes are answered with a 404.
+    // Rejections are logged with their body by `record_requests`
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(ErrorBody {
+            error: self.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::traktor::test_server::{api_request, TestServer};
+    use actix_web::http::StatusCode;
+
+    #[actix_web::test]
+    async fn test_extractor_errors_are_json() {
+        let server = TestServer::new();
+
+        let (status, body) = server.call(api_request("/updateDeck/A", "not json")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.starts_with(r#"{"error":"Invalid body: "#), "{}", body);
+
+        let (status, body) = server.call(api_request("/fx/x", "{}")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.starts_with(r#"{"error":"Invalid path: "#), "{}", body);
+    }
+}
@@ -0,0 +1,174 @@
+use actix_web::{get, rt, web, web::Bytes, HttpRequest, HttpResponse};
+use actix_ws::Message;
+use futures_util::{stream, Stream, StreamExt};
+use serde::Serialize;
+use std::{convert::Infallible, time::Duration};
+use tokio::time::timeout;
+
+use crate::traktor::{
+    AppState, Channel, ClientInfo, DeckContent, DeckID, FxUnitType, Hotcue, Knob, LoopState,
+    MasterClock, PlaybackState, TraktorState,
+};
+
+/// A change of the traktor state, published by the handlers in services.rs
+/// after they applied an update.
+///
+/// Serialized with a "type" tag, e.g.
+/// {"type":"KeyChanged","deck":"A","resulting_key":"Am"}
+#[derive(Serialize, Clone)]
+#[serde(tag = "type")]
+pub enum StateEvent {
+    // Full state, sent once to every new subscriber
+    Snapshot {
+        state: TraktorState,
+    },
+    DeckLoaded {
+        deck: DeckID,
+        content: Box<DeckContent>,
+        playback: PlaybackState,
+    },
+    DeckUpdated {
+        deck: DeckID,
+        playback: PlaybackState,
+    },
+    KeyChanged {
+        deck: DeckID,
+        resulting_key: String,
+    },
+    LoopChanged {
+        deck: DeckID,
+        r#loop: LoopState,
+    },
+    HotcueChanged {
+        deck: DeckID,
+        // Pad number [1,8]
+        hotcue: u8,
+        content: Option<Hotcue>,
+    },
+    FocusChanged {
+        deck: DeckID,
+    },
+    FxUnitChanged {
+        fx_unit: u8,
+        unit_type: FxUnitType,
+    },
+    KnobChanged {
+        fx_unit: u8,
+        knob: Knob,
+    },
+    ChannelChanged {
+        channel: Channel,
+    },
+    MasterClockChanged {
+        master_clock: MasterClock,
+    },
+    ClientChanged {
+        client: ClientInfo,
+    },
+}
+
+impl StateEvent {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    /// A server sent events message, the type is part of the json
+    /// so clients only need an onmessage handler
+    pub fn to_sse(&self) -> Bytes {
+        Bytes::from(format!("data: {}\n\n", self.to_json()))
+    }
+}
+
+/// Streams the state to websocket clients, a snapshot first
+/// and all changes afterwards
+#[get("/ws")]
+pub async fn websocket(
+    req: HttpRequest,
+    body: web::Payload,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    let (response, session, mut msg_stream) = actix_ws::handle(&req, body)?;
+
+    // Subscribe before taking the snapshot so nothing gets lost in between
+    let mut events = data.store.subscribe();
+    let snapshot = data.store.snapshot_event();
+
+    rt::spawn(async move {
+        let mut session = session;
+        if session.text(snapshot.to_json()).await.is_err() {
+            return;
+        }
+
+        loop {
+            tokio::select! {
+                event = data.store.next_event(&mut events) => {
+                    let Some(event) = event else {
+                        break;
+                    };
+                    if session.text(event.to_json()).await.is_err() {
+                        return;
+                    }
+                }
+                msg = msg_stream.recv() => {
+                    match msg {
+                        Some(Ok(Message::Ping(bytes))) => {
+                            if session.pong(&bytes).await.is_err() {
+                                return;
+                            }
+                        }
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                        // We don't expect anything from the clients
+                        Some(Ok(_)) => (),
+                    }
+                }
+            }
+        }
+
+        let _ = session.close(None).await;
+    });
+
+    Ok(response)
+}
+
+/// Same as the websocket but as server sent events, these work
+/// in a plain browser (e.g. an OBS browser source) via EventSource
+#[get("/events")]
+pub async fn server_sent_events(data: web::Data<AppState>) -> HttpResponse {
+    let events = data.store.subscribe();
+    let snapshot = data.store.snapshot_event();
+
+    let messages = stream::unfold(
+        (Some(snapshot), events, data),
+        |(pending, mut events, data)| async move {
+            let event = match pending {
+                Some(snapshot) => snapshot,
+                None => data.store.next_event(&mut events).await?,
+            };
+            Some((event.to_sse(), (None, events, data)))
+        },
+    );
+    sse_response(messages)
+}
+
+// Longest silence before a keep-alive comment is sent
+const SSE_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Streams the messages as server sent events until the stream ends,
+/// with a comment line in between to keep idle connections open
+pub fn sse_response(messages: impl Stream<Item = Bytes> + 'static) -> HttpResponse {
+    let stream = stream::unfold(Box::pin(messages), |mut messages| async move {
+        let message = match timeout(SSE_KEEP_ALIVE, messages.next()).await {
+            Ok(Some(message)) => message,
+            Ok(None) => return None,
+            Err(_) => Bytes::from_static(b": keep-alive\n\n"),
+        };
+        Some((Ok::<_, Infallible>(message), messages))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        // Overlays are usually opened from a local file
+        .insert_header(("Access-Control-Allow-Origin", "*"))
+        .streaming(stream)
+}
@@ -0,0 +1,202 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::Method,
+    middleware::Next,
+    post, web, HttpResponse,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::traktor::{AppState, ClientInfo, Section, StateEvent, StateStore};
+
+/// Version of the endpoints and payloads, has to match PROTOCOL_VERSION
+/// in ApiClient.js
+pub const PROTOCOL_VERSION: u32 = 4;
+
+// Sent by ApiClient.js with every request
+pub const PROTOCOL_HEADER: &str = "X-Push2Traktor-Protocol";
+
+/// Sent by ApiHello.qml every few seconds
+#[derive(Debug, Deserialize)]
+struct Hello {
+    #[serde(rename = "protocolVersion")]
+    protocol_version: u32,
+    // Null if traktor doesn't tell
+    #[serde(rename = "traktorVersion")]
+    traktor_version: Option<String>,
+}
+
+#[derive(Serialize)]
+struct HelloResponse {
+    #[serde(rename = "protocolVersion")]
+    protocol_version: u32,
+    compatible: bool,
+}
+
+#[post("/hello")]
+pub async fn receive_hello(event: web::Json<Hello>, data: web::Data<AppState>) -> HttpResponse {
+    let event = event.into_inner();
+    see_client(
+        &data.store,
+        Some(event.protocol_version),
+        event.traktor_version,
+    );
+
+    HttpResponse::Ok().json(HelloResponse {
+        protocol_version: PROTOCOL_VERSION,
+        compatible: event.protocol_version == PROTOCOL_VERSION,
+    })
+}
+
+/// Middleware reading the protocol version of every post, so even
+/// a client without ApiHello.qml is noticed. Mismatched requests are
+/// still handled, most endpoints might work anyway
+pub async fn check_protocol(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if req.method() == Method::POST {
+        // An unreadable version is as bad as a very old one
+        let version = req.headers().get(PROTOCOL_HEADER).map(|v| {
+            v.to_str()
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(0)
+        });
+        if let Some(data) = req.app_data::<web::Data<AppState>>() {
+            see_client(&data.store, version, None);
+        }
+    }
+
+    next.call(req).await
+}
+
+// Updates the client info and logs if it changed, the traktor version
+// is kept if the request didn't carry it. Requests without a protocol
+// version only count as an old client if no versioned one came before,
+// e.g. a plain curl doesn't downgrade a connected traktor
+fn see_client(store: &StateStore, protocol_version: Option<u32>, traktor_version: Option<String>) {
+    let mut changed = None;
+    store.update(Section::Client, |state| {
+        let client = &mut state.client;
+        let protocol_version = protocol_version.or(client.protocol_version).unwrap_or(0);
+        let traktor_version = traktor_version.or_else(|| client.traktor_version.clone());
+        if client.protocol_version == Some(protocol_version)
+            && client.traktor_version == traktor_version
+        {
+            return None;
+        }
+
+        client.protocol_version = Some(protocol_version);
+        client.traktor_version = traktor_version;
+        changed = Some(client.clone());
+        Some(StateEvent::ClientChanged {
+            client: client.clone(),
+        })
+    });
+
+    if let Some(client) = changed {
+        log_client(&client);
+    }
+}
+
+fn log_client(client: &ClientInfo) {
+    let traktor = client.traktor_version.as_deref().unwrap_or("unknown");
+    match client.protocol_version {
+        _ if client.is_compatible() => {
+            println!(
+                "Traktor {} connected, protocol {}",
+                traktor, PROTOCOL_VERSION
+            )
+        }
+        Some(0) => println!(
+            "Warning: the ApiClient.js in traktor doesn't send a protocol version, \
+             copy the D2 folder of this release to traktor"
+        ),
+        Some(version) => println!(
+            "Warning: traktor {} sends protocol {} but this driver expects {}, \
+             copy the D2 folder of this release to traktor",
+            traktor, version, PROTOCOL_VERSION
+        ),
+        None => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::traktor::test_server::TestServer;
+    use crate::traktor::DeckID;
+    use actix_web::{http::StatusCode, test::TestRequest};
+
+    #[actix_web::test]
+    async fn test_hello() {
+        let server = TestServer::new();
+        assert_eq!(server.state().client().protocol_version, None);
+
+        let hello = |version: u32| {
+            TestRequest::post()
+                .uri("/hello")
+                .insert_header(("X-Push2Traktor-Protocol", version.to_string()))
+                .set_json(
+                    serde_json::json!({"protocolVersion": version, "traktorVersion": "3.11.1 17"}),
+                )
+        };
+
+        let (status, body) = server.call(hello(PROTOCOL_VERSION - 1)).await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["protocolVersion"], PROTOCOL_VERSION);
+        assert_eq!(json["compatible"], false);
+        assert!(!server.state().client().is_compatible());
+
+        let (_, body) = server.call(hello(PROTOCOL_VERSION)).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["compatible"], true);
+
+        // Other requests keep the traktor version
+        server
+            .post("/updateMasterClock", r#"{"deck":null,"bpm":120}"#)
+            .await;
+        let client = server.state().client().clone();
+        assert_eq!(client.protocol_version, Some(PROTOCOL_VERSION));
+        assert_eq!(client.traktor_version.as_deref(), Some("3.11.1 17"));
+        assert!(client.is_compatible());
+    }
+
+    #[actix_web::test]
+    async fn test_old_client_is_incompatible() {
+        let server = TestServer::new();
+
+        // Handled anyway, only flagged
+        let req = TestRequest::post()
+            .uri("/updateMasterClock")
+            .set_json(serde_json::json!({"deck": "A", "bpm": 120}));
+        let (status, _) = server.call(req).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let state = server.state();
+        assert!(state.is_master_deck(&DeckID::A));
+        assert_eq!(state.client().protocol_version, Some(0));
+        assert!(!state.client().is_compatible());
+    }
+
+    #[actix_web::test]
+    async fn test_request_without_version_keeps_client() {
+        let server = TestServer::new();
+        server
+            .post("/updateMasterClock", r#"{"deck":"A","bpm":120}"#)
+            .await;
+
+        // e.g. a manual request while traktor is connected
+        let req = TestRequest::post()
+            .uri("/updateMasterClock")
+            .set_json(serde_json::json!({"deck": "B", "bpm": 120}));
+        assert_eq!(server.call(req).await.0, StatusCode::OK);
+
+        let client = server.state().client().clone();
+        assert_eq!(client.protocol_version, Some(PROTOCOL_VERSION));
+        assert!(client.is_compatible());
+    }
+}
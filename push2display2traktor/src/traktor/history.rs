@@ -0,0 +1,426 @@
+use actix_web::{get, web, HttpResponse};
+use serde::Serialize;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::traktor::{AppState, DeckContent, DeckID, StateEvent, StateStore, TraktorState};
+
+/// A track loaded during the session, times are seconds since the
+/// session started
+#[derive(Serialize, Clone)]
+pub struct HistoryEntry {
+    pub deck: DeckID,
+    pub content: DeckContent,
+    pub loaded_at: f64,
+    // First time the track was audible, None if it never was
+    pub on_air_at: Option<f64>,
+    // Last time the track went silent, None while it is still audible
+    pub off_air_at: Option<f64>,
+}
+
+impl HistoryEntry {
+    pub fn was_on_air(&self) -> bool {
+        self.on_air_at.is_some()
+    }
+}
+
+#[derive(Default, Clone)]
+struct DeckStatus {
+    // Index of the track currently loaded in the deck
+    entry: Option<usize>,
+    is_playing: bool,
+    is_channel_on_air: bool,
+}
+
+impl DeckStatus {
+    // Without any channel from traktor, e.g. with an external mixer,
+    // a playing deck counts as on air
+    fn is_on_air(&self, has_channels: bool) -> bool {
+        self.is_playing && (self.is_channel_on_air || !has_channels)
+    }
+}
+
+/// Remembers every track loaded during the session and when it was
+/// audible, fed by the change events of the store.
+#[derive(Serialize)]
+pub struct History {
+    // Unix time in seconds
+    started_at: f64,
+    tracks: Vec<HistoryEntry>,
+    #[serde(skip)]
+    start: Instant,
+    #[serde(skip)]
+    decks: [DeckStatus; 4],
+    // False until traktor sent a channel, e.g. with an external mixer
+    #[serde(skip)]
+    has_channels: bool,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(Instant::now())
+    }
+}
+
+impl History {
+    pub fn new(start: Instant) -> Self {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or(0.0);
+        Self {
+            started_at,
+            tracks: vec![],
+            start,
+            decks: Default::default(),
+            has_channels: false,
+        }
+    }
+
+    pub fn apply(&mut self, event: &StateEvent, now: Instant) {
+        match event {
+            StateEvent::Snapshot { state } => self.sync(state, now),
+            StateEvent::DeckLoaded {
+                deck,
+                content,
+                playback,
+            } => {
+                self.load(deck, content, now);
+                self.decks[deck.index()].is_playing = playback.is_playing;
+            }
+            StateEvent::DeckUpdated { deck, playback } => {
+                self.decks[deck.index()].is_playing = playback.is_playing;
+            }
+            StateEvent::ChannelChanged { channel } => {
+                if let Some(deck) = channel.deck_id() {
+                    self.decks[deck.index()].is_channel_on_air = channel.is_on_air;
+                    self.has_channels = true;
+                }
+            }
+            _ => return,
+        }
+        self.update_on_air(now);
+    }
+
+    // Catches up after missed events
+    fn sync(&mut self, state: &TraktorState, now: Instant) {
+        for deck in state.iter_all_decks() {
+            if let Some(content) = &deck.content {
+                // Snapshots repeat the loaded track, also after a lag
+                let current = self.decks[deck.id.index()].entry.map(|i| &self.tracks[i]);
+                if current.is_none_or(|c| c.content.file_path != content.file_path) {
+                    self.load(&deck.id, content, now);
+                }
+            }
+            self.decks[deck.id.index()].is_playing = deck.playback.is_playing;
+        }
+        for channel in state.iter_all_channels() {
+            if let Some(deck) = channel.deck_id() {
+                self.decks[deck.index()].is_channel_on_air = channel.is_on_air;
+            }
+        }
+        self.has_channels |= state.has_channels();
+    }
+
+    fn load(&mut self, deck: &DeckID, content: &DeckContent, now: Instant) {
+        let status = &mut self.decks[deck.index()];
+
+        // Traktor sometimes sends the same load twice
+        if let Some(current) = status.entry.map(|i| &self.tracks[i]) {
+            if current.content.file_path == content.file_path && !current.was_on_air() {
+                return;
+            }
+        }
+
+        // The previous track can't be audible anymore
+        let time = (now - self.start).as_secs_f64();
+        if status.is_on_air(self.has_channels) {
+            if let Some(current) = status.entry {
+                self.tracks[current].off_air_at = Some(time);
+            }
+        }
+
+        status.entry = Some(self.tracks.len());
+        status.is_playing = false;
+        self.tracks.push(HistoryEntry {
+            deck: deck.clone(),
+            content: content.clone(),
+            loaded_at: time,
+            on_air_at: None,
+            off_air_at: None,
+        });
+    }
+
+    fn update_on_air(&mut self, now: Instant) {
+        let time = (now - self.start).as_secs_f64();
+        for status in &self.decks {
+            let Some(entry) = status.entry.map(|i| &mut self.tracks[i]) else {
+                continue;
+            };
+            let is_on_air = status.is_on_air(self.has_channels);
+            let was_on_air = entry.was_on_air() && entry.off_air_at.is_none();
+            if is_on_air && !was_on_air {
+                entry.on_air_at.get_or_insert(time);
+                entry.off_air_at = None;
+            } else if !is_on_air && was_on_air {
+                entry.off_air_at = Some(time);
+            }
+        }
+    }
+
+    /// All loaded tracks, the times as hh:mm:ss since the start
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "deck,artist,title,remixer,label,key,bpm,loaded,on_air,off_air,file_path\n",
+        );
+        for entry in &self.tracks {
+            let content = &entry.content;
+            let fields = [
+                format!("{:?}", entry.deck),
+                content.artist.clone(),
+                content.title.clone(),
+                content.remixer.clone(),
+                content.label.clone(),
+                content.key_text.clone(),
+                format!("{:.2}", content.bpm),
+                format_time(Some(entry.loaded_at)),
+                format_time(entry.on_air_at),
+                format_time(entry.off_air_at),
+                content.file_path.clone(),
+            ];
+            let fields: Vec<String> = fields.iter().map(|f| escape_csv(f)).collect();
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Playlist of the tracks which were on air, in the order they were played
+    pub fn to_m3u(&self) -> String {
+        let mut played: Vec<&HistoryEntry> =
+            self.tracks.iter().filter(|e| e.was_on_air()).collect();
+        played.sort_by(|a, b| a.on_air_at.partial_cmp(&b.on_air_at).unwrap());
+
+        let mut m3u = String::from("#EXTM3U\n");
+        for entry in played {
+            let content = &entry.content;
+            m3u.push_str(&format!(
+                "#EXTINF:{:.0},{} - {}\n{}\n",
+                content.length, content.artist, content.title, content.file_path
+            ));
+        }
+        m3u
+    }
+}
+
+fn format_time(time: Option<f64>) -> String {
+    match time {
+        Some(t) => {
+            let t = t as u64;
+            format!("{:02}:{:02}:{:02}", t / 3600, t / 60 % 60, t % 60)
+        }
+        None => String::new(),
+    }
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Keeps the history up to date until the store is dropped
+pub async fn record_history(store: Arc<StateStore>, history: Arc<Mutex<History>>) {
+    let mut events = store.subscribe();
+    let snapshot = store.snapshot_event();
+    history.lock().unwrap().apply(&snapshot, Instant::now());
+
+    while let Some(event) = store.next_event(&mut events).await {
+        history.lock().unwrap().apply(&event, Instant::now());
+    }
+}
+
+#[get("/history.json")]
+pub async fn get_history_json(data: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(&*data.history.lock().unwrap())
+}
+
+#[get("/history.csv")]
+pub async fn get_history_csv(data: web::Data<AppState>) -> HttpResponse {
+    let csv = data.history.lock().unwrap().to_csv();
+    HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header((
+            "Content-Disposition",
+            "attachment; filename=\"setlist.csv\"",
+        ))
+        .body(csv)
+}
+
+#[get("/history.m3u")]
+pub async fn get_history_m3u(data: web::Data<AppState>) -> HttpResponse {
+    let m3u = data.history.lock().unwrap().to_m3u();
+    HttpResponse::Ok()
+        .content_type("audio/x-mpegurl; charset=utf-8")
+        .insert_header((
+            "Content-Disposition",
+            "attachment; filename=\"setlist.m3u\"",
+        ))
+        .body(m3u)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::traktor::test_server::TestServer;
+    use crate::traktor::{Channel, MixerKnobs, PlaybackState};
+    use actix_web::{http::StatusCode, test::TestRequest};
+    use std::time::Duration;
+
+    fn content(title: &str) -> Box<DeckContent> {
+        Box::new(DeckContent {
+            artist: "Artist, The".to_string(),
+            key_text: "8A".to_string(),
+            ..DeckContent::for_test(title, 124.0, 300.0)
+        })
+    }
+
+    fn loaded(deck: DeckID, title: &str) -> StateEvent {
+        StateEvent::DeckLoaded {
+            deck,
+            content: content(title),
+            playback: PlaybackState::default(),
+        }
+    }
+
+    fn playing(deck: DeckID, is_playing: bool) -> StateEvent {
+        StateEvent::DeckUpdated {
+            deck,
+            playback: PlaybackState {
+                is_playing,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn on_air(id: u8, is_on_air: bool) -> StateEvent {
+        StateEvent::ChannelChanged {
+            channel: Channel {
+                id,
+                is_on_air,
+                on_air_level: is_on_air as u8 as f64,
+                mixer: MixerKnobs::default(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut history = History::new(start);
+
+        history.apply(&loaded(DeckID::A, "First"), at(0));
+        history.apply(&on_air(1, true), at(1));
+        history.apply(&playing(DeckID::A, true), at(2));
+        history.apply(&loaded(DeckID::B, "Second"), at(60));
+        // Loaded twice by traktor
+        history.apply(&loaded(DeckID::B, "Second"), at(61));
+        history.apply(&playing(DeckID::B, true), at(90));
+        history.apply(&on_air(2, true), at(100));
+        history.apply(&on_air(1, false), at(120));
+        // Never played
+        history.apply(&loaded(DeckID::A, "Third"), at(130));
+
+        let tracks = &history.tracks;
+        assert_eq!(tracks.len(), 3);
+        assert_eq!(tracks[0].on_air_at, Some(2.0));
+        assert_eq!(tracks[0].off_air_at, Some(120.0));
+        assert_eq!(tracks[1].loaded_at, 60.0);
+        assert_eq!(tracks[1].on_air_at, Some(100.0));
+        assert_eq!(tracks[1].off_air_at, None);
+        assert!(!tracks[2].was_on_air());
+    }
+
+    #[test]
+    fn test_history_repeated_snapshot() {
+        let start = Instant::now();
+        let mut history = History::new(start);
+
+        let mut state = TraktorState::default();
+        state.decks[0].content = Some(*content("First"));
+        state.decks[0].playback.is_playing = true;
+        state.channels[0].is_on_air = true;
+        state.has_channels = true;
+        let snapshot = StateEvent::Snapshot { state };
+
+        history.apply(&snapshot, start);
+        history.apply(&snapshot, start + Duration::from_secs(30));
+
+        assert_eq!(history.tracks.len(), 1);
+        assert_eq!(history.tracks[0].on_air_at, Some(0.0));
+        assert_eq!(history.tracks[0].off_air_at, None);
+    }
+
+    #[test]
+    fn test_history_without_channels() {
+        let start = Instant::now();
+        let mut history = History::new(start);
+
+        history.apply(&loaded(DeckID::C, "First"), start);
+        history.apply(&playing(DeckID::C, true), start + Duration::from_secs(5));
+        history.apply(&playing(DeckID::C, false), start + Duration::from_secs(65));
+
+        assert_eq!(history.tracks[0].on_air_at, Some(5.0));
+        assert_eq!(history.tracks[0].off_air_at, Some(65.0));
+    }
+
+    #[test]
+    fn test_exports() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut history = History::new(start);
+
+        history.apply(&loaded(DeckID::A, "First"), at(0));
+        history.apply(&loaded(DeckID::B, "Second"), at(10));
+        history.apply(&playing(DeckID::B, true), at(20));
+        history.apply(&playing(DeckID::A, true), at(3725));
+
+        let csv = history.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[1],
+            r#"A,"Artist, The",First,,,8A,124.00,00:00:00,01:02:05,,/Music/First.mp3"#
+        );
+
+        assert_eq!(
+            history.to_m3u(),
+            "#EXTM3U\n\
+             #EXTINF:300,Artist, The - Second\n/Music/Second.mp3\n\
+             #EXTINF:300,Artist, The - First\n/Music/First.mp3\n"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_history_exports() {
+        let server = TestServer::new();
+
+        let (status, body) = server.call(TestRequest::get().uri("/history.csv")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.starts_with("deck,artist,title,"));
+
+        let (status, body) = server.call(TestRequest::get().uri("/history.m3u")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "#EXTM3U\n");
+
+        let (status, body) = server.call(TestRequest::get().uri("/history.json")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains(r#""tracks":[]"#));
+    }
+}
@@ -0,0 +1,236 @@
+use clap::ValueEnum;
+
+// Names by pitch class, flats where traktor uses them
+const MAJOR_NAMES: [&str; 12] = [
+    "C", "Db", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B",
+];
+const MINOR_NAMES: [&str; 12] = [
+    "Cm", "C#m", "Dm", "Ebm", "Em", "Fm", "F#m", "Gm", "G#m", "Am", "Bbm", "Bm",
+];
+
+/// How keys are written on the display
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyNotation {
+    /// e.g. Am, F#
+    Musical,
+    /// e.g. 8A, 2B
+    Camelot,
+    /// e.g. 1m, 7d
+    OpenKey,
+}
+
+/// A musical key, parsed from any notation traktor might send
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Key {
+    // Pitch class of the root, C is 0
+    pitch: u8,
+    minor: bool,
+}
+
+/// How well two keys mix, see `Key::compatibility`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compatibility {
+    Same,
+    // One step on the camelot wheel, the sign is the direction
+    Adjacent(i8),
+    // Same number, major to minor or back
+    Relative,
+    // Two steps clockwise, a whole tone up
+    EnergyBoost,
+}
+
+impl Key {
+    pub fn new(pitch: u8, minor: bool) -> Self {
+        Self {
+            pitch: pitch % 12,
+            minor,
+        }
+    }
+
+    /// Parses musical ("Am", "F#", "Ebm"), camelot ("8A"), open key ("1m")
+    /// or traktor's key index (0-11 major from C, 12-23 minor)
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+
+        // Index as sent by the key properties, can have decimals
+        if let Ok(index) = text.parse::<f64>() {
+            let index = index.round();
+            return (0.0..24.0)
+                .contains(&index)
+                .then(|| Self::new(index as u8 % 12, index >= 12.0));
+        }
+
+        let digits = text.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits > 0 {
+            let number: u8 = text[..digits].parse().ok()?;
+            if !(1..=12).contains(&number) {
+                return None;
+            }
+            return match text[digits..].to_ascii_lowercase().as_str() {
+                "a" => Some(Self::from_camelot(number, true)),
+                "b" => Some(Self::from_camelot(number, false)),
+                "m" => Some(Self::from_camelot((number + 6) % 12 + 1, true)),
+                "d" => Some(Self::from_camelot((number + 6) % 12 + 1, false)),
+                _ => None,
+            };
+        }
+
+        let mut chars = text.chars();
+        let mut pitch = match chars.next()?.to_ascii_uppercase() {
+            'C' => 0,
+            'D' => 2,
+            'E' => 4,
+            'F' => 5,
+            'G' => 7,
+            'A' => 9,
+            'B' => 11,
+            _ => return None,
+        };
+        let mut rest = chars.as_str();
+        if let Some(r) = rest.strip_prefix(['#', '♯']) {
+            pitch += 1;
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix(['b', '♭']) {
+            pitch += 11;
+            rest = r;
+        }
+        let minor = match rest.trim().to_ascii_lowercase().as_str() {
+            "" | "maj" | "major" => false,
+            "m" | "min" | "minor" => true,
+            _ => return None,
+        };
+        Some(Self::new(pitch, minor))
+    }
+
+    fn from_camelot(number: u8, minor: bool) -> Self {
+        // 7 fifths are a semitone, 8B is C major
+        let major = (number as i32 - 8) * 7;
+        let pitch = if minor { major - 3 } else { major };
+        Self::new(pitch.rem_euclid(12) as u8, minor)
+    }
+
+    /// Position on the camelot wheel, 1-12
+    pub fn camelot_number(&self) -> u8 {
+        // The relative major has the same number
+        let major = if self.minor {
+            (self.pitch + 3) % 12
+        } else {
+            self.pitch
+        };
+        (major * 7 % 12 + 7) % 12 + 1
+    }
+
+    pub fn format(&self, notation: KeyNotation) -> String {
+        match notation {
+            KeyNotation::Musical if self.minor => MINOR_NAMES[self.pitch as usize].to_string(),
+            KeyNotation::Musical => MAJOR_NAMES[self.pitch as usize].to_string(),
+            KeyNotation::Camelot => {
+                let letter = if self.minor { 'A' } else { 'B' };
+                format!("{}{}", self.camelot_number(), letter)
+            }
+            KeyNotation::OpenKey => {
+                let letter = if self.minor { 'm' } else { 'd' };
+                format!("{}{}", (self.camelot_number() + 4) % 12 + 1, letter)
+            }
+        }
+    }
+
+    /// How mixing into this key from `other` sounds, None if they clash
+    pub fn compatibility(&self, other: &Key) -> Option<Compatibility> {
+        // Steps clockwise from the other key, -5 to 6
+        let steps = (self.camelot_number() as i8 - other.camelot_number() as i8 + 17) % 12 - 5;
+        match (steps, self.minor == other.minor) {
+            (0, true) => Some(Compatibility::Same),
+            (1 | -1, true) => Some(Compatibility::Adjacent(steps)),
+            (0, false) => Some(Compatibility::Relative),
+            (2, true) => Some(Compatibility::EnergyBoost),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn key(text: &str) -> Key {
+        Key::parse(text).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(key("Am"), Key::new(9, true));
+        assert_eq!(key("F#"), Key::new(6, false));
+        assert_eq!(key("Ebm"), Key::new(3, true));
+        assert_eq!(key("Bb"), Key::new(10, false));
+        assert_eq!(key("C minor"), Key::new(0, true));
+        assert_eq!(key("8A"), Key::new(9, true));
+        assert_eq!(key("1B"), Key::new(11, false));
+        assert_eq!(key("1m"), Key::new(9, true));
+        assert_eq!(key("6d"), Key::new(11, false));
+        assert_eq!(key("21"), Key::new(9, true));
+        assert_eq!(key("7.0"), Key::new(7, false));
+
+        for text in ["", "H", "13A", "0B", "8C", "24", "Am7"] {
+            assert_eq!(Key::parse(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        // Every key survives a round trip through every notation
+        for pitch in 0..12 {
+            for minor in [false, true] {
+                let key = Key::new(pitch, minor);
+                for notation in [
+                    KeyNotation::Musical,
+                    KeyNotation::Camelot,
+                    KeyNotation::OpenKey,
+                ] {
+                    assert_eq!(Key::parse(&key.format(notation)), Some(key));
+                }
+            }
+        }
+
+        assert_eq!(key("Am").format(KeyNotation::Camelot), "8A");
+        assert_eq!(key("Am").format(KeyNotation::OpenKey), "1m");
+        assert_eq!(key("E").format(KeyNotation::Camelot), "12B");
+        assert_eq!(key("E").format(KeyNotation::OpenKey), "5d");
+        assert_eq!(key("10A").format(KeyNotation::Musical), "Bm");
+    }
+
+    #[test]
+    fn test_compatibility() {
+        let am = key("8A");
+        assert_eq!(key("Am").compatibility(&am), Some(Compatibility::Same));
+        assert_eq!(
+            key("9A").compatibility(&am),
+            Some(Compatibility::Adjacent(1))
+        );
+        assert_eq!(
+            key("7A").compatibility(&am),
+            Some(Compatibility::Adjacent(-1))
+        );
+        assert_eq!(key("8B").compatibility(&am), Some(Compatibility::Relative));
+        assert_eq!(
+            key("10A").compatibility(&am),
+            Some(Compatibility::EnergyBoost)
+        );
+        assert_eq!(key("6A").compatibility(&am), None);
+        assert_eq!(key("9B").compatibility(&am), None);
+
+        // Across the 12 to 1 border
+        assert_eq!(
+            key("1A").compatibility(&key("12A")),
+            Some(Compatibility::Adjacent(1))
+        );
+        assert_eq!(
+            key("2B").compatibility(&key("12B")),
+            Some(Compatibility::EnergyBoost)
+        );
+    }
+}
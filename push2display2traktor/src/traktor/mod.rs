@@ -1,20 +1,80 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use actix_web::{web, App, HttpServer};
-use serde::Serialize;
-use services::{get_state, receive_deck_loaded_event, receive_deck_update_event, receive_fx_event};
+use crate::config::Config;
+use crate::metrics::Metrics;
+use actix_web::{middleware::from_fn, web, App, HttpServer};
+use error::{json_error, path_error};
+use events::{server_sent_events, websocket, StateEvent};
+use handshake::{check_protocol, receive_hello};
+use history::{get_history_csv, get_history_json, get_history_m3u, record_history, History};
+use keys::Key;
+use monitoring::{count_events, get_metrics};
+use now_playing::{follow_now_playing, get_now_playing, now_playing_events, overlay, NowPlaying};
+use serde::{Deserialize, Serialize};
+use services::{
+    get_state, receive_channel_update_event, receive_deck_loaded_event, receive_deck_update_event,
+    receive_focus_event, receive_fx_event, receive_hotcue_event, receive_master_clock_event,
+};
+use persistence::persist_state;
+use playhead::Playhead;
+use recorder::{record_requests, Recorder};
 use std::slice::Iter;
-use tokio::sync::Mutex;
+use std::time::Instant;
+use tokio::sync::watch;
+pub use client::ApiClient;
+pub use handshake::{PROTOCOL_HEADER, PROTOCOL_VERSION};
+pub use persistence::{load_state, save_state};
+pub use recorder::replay;
+pub use simulator::simulate;
+pub use store::{Changes, Section, StateStore, Versions};
 
+pub mod beats;
+mod client;
+mod error;
+mod events;
+mod handshake;
+mod history;
+pub mod keys;
+mod monitoring;
+mod now_playing;
+mod persistence;
+mod playhead;
+mod recorder;
 mod services;
+mod simulator;
+mod store;
+pub mod tempo;
+#[cfg(test)]
+mod test_server;
 
-#[derive(Serialize, Clone)]
+// Number of fx units, knobs per unit (drywet + 3) and mixer channels
+pub const FX_UNITS: u8 = 4;
+pub const KNOBS_PER_FX_UNIT: u8 = 4;
+pub const CHANNELS: u8 = 4;
+// Hotcues per deck, one per pad
+pub const HOTCUES: u8 = 8;
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct TraktorState {
     fx_units: Vec<FXUnit>,
     decks: Vec<Deck>,
+    channels: Vec<Channel>,
+    // False until traktor sent a channel, e.g. with an external mixer
+    #[serde(skip)]
+    has_channels: bool,
+    master_clock: MasterClock,
+    // Deck the D2 mapping is focused on, None until traktor tells us
+    #[serde(default)]
+    focused_deck: Option<DeckID>,
+    // What is sending to us, not restored as it might have been updated
+    #[serde(skip_deserializing)]
+    client: ClientInfo,
+    // Bumped by the store on every change
+    #[serde(skip)]
+    versions: Versions,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct FXUnit {
     // Identifier in traktor [1,4]
     id: u8,
@@ -22,17 +82,20 @@ pub struct FXUnit {
 
     // Each fx unit has four knobs (drywet + 3*effect)
     knobs: Vec<Knob>,
+    // Knobs not sent again since a restore, see TraktorState::mark_stale
+    #[serde(default)]
+    pub stale: bool,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub enum FxUnitType {
     Group,
     Single,
     UNK,
 }
 
-#[derive(Serialize, Clone)]
-struct Knob {
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Knob {
     // Identifier in traktor [1,3]
     // 0 for dry wet
     id: u8,
@@ -41,14 +104,137 @@ struct Knob {
     fx_name: String,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Deck {
     pub id: DeckID,
     // If deck is loaded
     pub content: Option<DeckContent>,
+    pub playback: PlaybackState,
+    #[serde(default)]
+    pub r#loop: LoopState,
+    // Indexed by pad, None if the pad holds no hotcue
+    #[serde(default)]
+    pub hotcues: [Option<Hotcue>; HOTCUES as usize],
+    // Interpolates the elapsed time between updates
+    #[serde(skip)]
+    pub playhead: Playhead,
+    // Not updated since a restore, see TraktorState::mark_stale
+    #[serde(default)]
+    pub stale: bool,
 }
 
-#[derive(Serialize, Clone, PartialEq, Eq)]
+impl Deck {
+    pub fn new(id: DeckID) -> Self {
+        Self {
+            id,
+            content: None,
+            playback: PlaybackState::default(),
+            r#loop: LoopState::default(),
+            hotcues: Default::default(),
+            playhead: Playhead::default(),
+            stale: false,
+        }
+    }
+
+    /// Playhead position in seconds, cheap enough to be sampled every frame
+    pub fn position_at(&self, now: Instant) -> f64 {
+        let position = self.playhead.position_at(now).max(0.0);
+        match &self.content {
+            Some(c) if c.length > 0.0 => position.min(c.length),
+            _ => position,
+        }
+    }
+
+    /// Seconds until the end of the track, None without a track length
+    pub fn remaining_at(&self, now: Instant) -> Option<f64> {
+        let length = self.content.as_ref()?.length;
+        (length > 0.0).then(|| length - self.position_at(now))
+    }
+}
+
+/// Transport state of a deck, kept current by the updateDeck events
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PlaybackState {
+    pub is_playing: bool,
+    pub is_synced: bool,
+    pub is_key_lock_on: bool,
+    // Ratio to the original track tempo, 1.0 is unchanged
+    pub tempo: f64,
+    // Playhead position in seconds
+    pub elapsed_time: f64,
+    // Position of the next cue point in seconds, None if there is none
+    pub next_cue_pos: Option<f64>,
+}
+
+impl PlaybackState {
+    /// Track seconds per second
+    pub fn rate(&self) -> f64 {
+        if self.is_playing {
+            self.tempo
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Default for PlaybackState {
+    fn default() -> Self {
+        Self {
+            is_playing: false,
+            is_synced: false,
+            is_key_lock_on: false,
+            tempo: 1.0,
+            elapsed_time: 0.0,
+            next_cue_pos: None,
+        }
+    }
+}
+
+/// Loop and beatjump settings of a deck, sizes are in beats and None
+/// if traktor is set to something else, e.g. a fine beatjump
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct LoopState {
+    pub is_active: bool,
+    pub size: Option<f64>,
+    pub beatjump_size: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Hotcue {
+    pub r#type: HotcueType,
+    // Start in seconds
+    pub position: f64,
+    // Length in seconds, only set for loops
+    pub length: f64,
+    pub name: String,
+}
+
+/// Hotcue types in the order traktor numbers them
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HotcueType {
+    Cue,
+    FadeIn,
+    FadeOut,
+    Load,
+    Grid,
+    Loop,
+}
+
+impl HotcueType {
+    pub fn from_traktor(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(HotcueType::Cue),
+            1 => Some(HotcueType::FadeIn),
+            2 => Some(HotcueType::FadeOut),
+            3 => Some(HotcueType::Load),
+            4 => Some(HotcueType::Grid),
+            5 => Some(HotcueType::Loop),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
 pub enum DeckID {
     A,
     B,
@@ -61,6 +247,15 @@ impl DeckID {
         static DECK_IDS: [DeckID; 4] = [DeckID::A, DeckID::B, DeckID::C, DeckID::D];
         DECK_IDS.iter()
     }
+    /// Position in the list of decks
+    pub fn index(&self) -> usize {
+        match self {
+            DeckID::A => 0,
+            DeckID::B => 1,
+            DeckID::C => 2,
+            DeckID::D => 3,
+        }
+    }
     pub fn from_char(c: char) -> Option<DeckID> {
         match c {
             'a' | 'A' => Some(DeckID::A),
@@ -72,22 +267,162 @@ impl DeckID {
     }
 }
 
-#[derive(Serialize, Clone)]
+/// A mixer channel, channel n is routed from deck n
+/// in the default traktor setup
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Channel {
+    // Identifier in traktor [1,4]
+    pub id: u8,
+    // Volume up and not cut by the crossfader
+    pub is_on_air: bool,
+    // Volume scaled by the crossfader [0,1]
+    pub on_air_level: f64,
+    // Missing in states saved before the knobs were sent
+    #[serde(default)]
+    pub mixer: MixerKnobs,
+}
+
+/// Knob positions of a mixer channel, all in [0,1] with 0.5 being
+/// the center detent, e.g. 0 dB gain or an open filter
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MixerKnobs {
+    pub gain: f64,
+    pub eq_high: f64,
+    pub eq_mid: f64,
+    pub eq_low: f64,
+    // Below 0.5 low pass, above high pass
+    pub filter: f64,
+    pub is_filter_on: bool,
+}
+
+impl Default for MixerKnobs {
+    fn default() -> Self {
+        Self {
+            gain: 0.5,
+            eq_high: 0.5,
+            eq_mid: 0.5,
+            eq_low: 0.5,
+            filter: 0.5,
+            is_filter_on: false,
+        }
+    }
+}
+
+impl Channel {
+    pub fn new(id: u8) -> Self {
+        Self {
+            id,
+            is_on_air: false,
+            on_air_level: 0.0,
+            mixer: MixerKnobs::default(),
+        }
+    }
+
+    /// The deck feeding this channel, None for ids traktor doesn't have
+    pub fn deck_id(&self) -> Option<DeckID> {
+        match self.id {
+            1 => Some(DeckID::A),
+            2 => Some(DeckID::B),
+            3 => Some(DeckID::C),
+            4 => Some(DeckID::D),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MasterClock {
+    // Deck the master tempo follows, None if the internal clock is master
+    pub deck: Option<DeckID>,
+    pub bpm: f64,
+}
+
+impl Default for MasterClock {
+    fn default() -> Self {
+        Self {
+            deck: None,
+            bpm: 0.0,
+        }
+    }
+}
+
+/// The ApiClient.js in traktor's D2 folder as seen by the server
+#[derive(Serialize, Clone, Default, Debug, PartialEq)]
+pub struct ClientInfo {
+    // None until the first request, 0 for clients older than the handshake
+    pub protocol_version: Option<u32>,
+    // As reported by /hello
+    pub traktor_version: Option<String>,
+}
+
+impl ClientInfo {
+    /// False if the client sent a version this server doesn't speak
+    pub fn is_compatible(&self) -> bool {
+        self.protocol_version
+            .is_none_or(|version| version == PROTOCOL_VERSION)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct DeckContent {
     pub title: String,
-    file_path: String,
+    pub file_path: String,
     pub artist: String,
-    album: String,
+    pub album: String,
     pub genre: String,
-    label: String,
-    key: String,
+    pub comment: String,
+    pub comment2: String,
+    pub label: String,
+    pub mix: String,
+    pub remixer: String,
+    pub key: String,
+    // Legacy key notation, e.g. "8A" or "Am"
+    pub key_text: String,
     pub resulting_key: String,
-    length: f64,
+    // Offset of the first beat in seconds
+    pub grid_offset: f64,
+    // Original track tempo
+    pub bpm: f64,
+    // Track length in seconds
+    pub length: f64,
+}
+
+impl DeckContent {
+    /// The key traktor plays the track in, falls back to the analysed
+    /// key and the legacy text if the resulting one can't be read
+    pub fn musical_key(&self) -> Option<Key> {
+        [&self.resulting_key, &self.key, &self.key_text]
+            .into_iter()
+            .find_map(|key| Key::parse(key))
+    }
+
+    /// A track without any tags, for tests which only need a few fields
+    #[cfg(test)]
+    pub fn for_test(title: &str, bpm: f64, length: f64) -> Self {
+        Self {
+            title: title.to_string(),
+            file_path: format!("/Music/{}.mp3", title),
+            artist: String::new(),
+            album: String::new(),
+            genre: String::new(),
+            comment: String::new(),
+            comment2: String::new(),
+            label: String::new(),
+            mix: String::new(),
+            remixer: String::new(),
+            key: String::new(),
+            key_text: String::new(),
+            resulting_key: String::new(),
+            grid_offset: 0.0,
+            bpm,
+            length,
+        }
+    }
 }
 
 impl FXUnit {
     pub fn new(id: u8) -> Self {
-        let knobs: Vec<Knob> = (0..4)
+        let knobs: Vec<Knob> = (0..KNOBS_PER_FX_UNIT)
             .into_iter()
             .map(|i| Knob {
                 id: i as u8,
@@ -101,24 +436,56 @@ impl FXUnit {
             id,
             knobs,
             r#type: FxUnitType::UNK,
+            stale: false,
         }
     }
 }
 
 impl Default for TraktorState {
     fn default() -> Self {
-        let fx_units: Vec<FXUnit> = (1..5).into_iter().map(|i| FXUnit::new(i as u8)).collect();
-        let decks: Vec<Deck> = DeckID::iter()
-            .map(|id| Deck {
-                id: id.clone(),
-                content: None,
-            })
-            .collect();
-        Self { fx_units, decks }
+        let fx_units: Vec<FXUnit> = (1..=FX_UNITS).map(FXUnit::new).collect();
+        let decks: Vec<Deck> = DeckID::iter().map(|id| Deck::new(id.clone())).collect();
+        let channels: Vec<Channel> = (1..=CHANNELS).map(Channel::new).collect();
+        Self {
+            fx_units,
+            decks,
+            channels,
+            has_channels: false,
+            master_clock: MasterClock::default(),
+            focused_deck: None,
+            client: ClientInfo::default(),
+            versions: Versions::default(),
+        }
     }
 }
 
 impl TraktorState {
+    pub fn versions(&self) -> &Versions {
+        &self.versions
+    }
+
+    /// Marks the decks and fx units as stale until traktor sends them
+    /// again, used for a state restored from disk. A stale deck or unit
+    /// is drawn dimmed and is confirmed by its own next update only, the
+    /// handlers clear the flag. The playheads stay at the saved position
+    /// until then.
+    ///
+    /// The mixer and master clock are reset instead, an outdated on air
+    /// or master deck would end up in the history and the overlay.
+    pub fn mark_stale(&mut self) {
+        let now = Instant::now();
+        for deck in &mut self.decks {
+            deck.stale = true;
+            deck.playhead = Playhead::new(deck.playback.elapsed_time, 0.0, now);
+        }
+        for fx_unit in &mut self.fx_units {
+            fx_unit.stale = true;
+        }
+        self.channels = (1..=CHANNELS).map(Channel::new).collect();
+        self.has_channels = false;
+        self.master_clock = MasterClock::default();
+    }
+
     fn iter_all_knobs(&self) -> impl Iterator<Item = &Knob> {
         self.fx_units.iter().flat_map(|unit| unit.knobs.iter())
     }
@@ -134,33 +501,198 @@ impl TraktorState {
         self.iter_all_knobs().map(|k| &k.value)
     }
 
+    /// Per knob, true if its fx unit wasn't confirmed since a restore
+    pub fn iter_knob_stale(&self) -> impl Iterator<Item = bool> + '_ {
+        self.fx_units
+            .iter()
+            .flat_map(|unit| unit.knobs.iter().map(|_| unit.stale))
+    }
+
     pub fn iter_deck_content(&self) -> impl Iterator<Item = &Option<DeckContent>> {
         self.iter_all_decks().map(|x| &x.content)
     }
+
+    pub fn iter_all_channels(&self) -> impl Iterator<Item = &Channel> {
+        self.channels.iter()
+    }
+
+    /// Without channels the mixer can't tell which decks are audible
+    pub fn has_channels(&self) -> bool {
+        self.has_channels
+    }
+
+    pub fn master_clock(&self) -> &MasterClock {
+        &self.master_clock
+    }
+
+    pub fn client(&self) -> &ClientInfo {
+        &self.client
+    }
+
+    /// The deck the D2 mapping is focused on, the master deck
+    /// as long as the focus wasn't reported
+    pub fn focused_deck(&self) -> Option<&Deck> {
+        let id = self.focused_deck.as_ref().or(self.master_clock.deck.as_ref())?;
+        self.decks.get(id.index())
+    }
+
+    /// True for the two decks the display shows, the pair the D2 focus is
+    /// in, A and B or C and D. Falls back to the master deck and then A and B
+    pub fn is_deck_shown(&self, deck_id: &DeckID) -> bool {
+        let focus = self
+            .focused_deck
+            .as_ref()
+            .or(self.master_clock.deck.as_ref());
+        let is_upper = |id: &DeckID| matches!(id, DeckID::A | DeckID::B);
+        is_upper(deck_id) == focus.is_none_or(is_upper)
+    }
+
+    pub fn is_master_deck(&self, deck_id: &DeckID) -> bool {
+        self.master_clock.deck.as_ref() == Some(deck_id)
+    }
+
+    /// True if the channel the deck is routed to is audible
+    pub fn is_deck_on_air(&self, deck_id: &DeckID) -> bool {
+        self.iter_all_channels()
+            .any(|c| c.is_on_air && c.deck_id().as_ref() == Some(deck_id))
+    }
 }
 
 /// App state is basically a wrapper for everything on the server
-/// I decided to just hold one arc mutex
+/// The traktor state lives in the store, which also broadcasts
+/// every change to the render loop and the websocket and sse clients
 pub struct AppState {
-    pub traktor: Arc<Mutex<TraktorState>>,
+    pub store: Arc<StateStore>,
+    // Set if the requests from traktor should be written to a file
+    pub recorder: Option<Arc<Recorder>>,
+    pub history: Arc<Mutex<History>>,
+    pub now_playing: Arc<watch::Sender<Option<NowPlaying>>>,
+    // Shared with the render loop
+    pub metrics: Arc<Metrics>,
+}
+
+impl AppState {
+    pub fn new(store: Arc<StateStore>) -> Self {
+        Self {
+            store,
+            recorder: None,
+            history: Arc::new(Mutex::new(History::default())),
+            now_playing: Arc::new(watch::Sender::new(None)),
+            metrics: Arc::new(Metrics::default()),
+        }
+    }
+}
+
+/// Registers all endpoints, also used to test them without a server
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(web::JsonConfig::default().error_handler(json_error))
+        .app_data(web::PathConfig::default().error_handler(path_error))
+        .service(get_state)
+        .service(receive_hello)
+        .service(receive_fx_event)
+        .service(receive_deck_loaded_event)
+        .service(receive_deck_update_event)
+        .service(receive_hotcue_event)
+        .service(receive_focus_event)
+        .service(receive_channel_update_event)
+        .service(receive_master_clock_event)
+        .service(websocket)
+        .service(server_sent_events)
+        .service(get_history_json)
+        .service(get_history_csv)
+        .service(get_history_m3u)
+        .service(get_now_playing)
+        .service(now_playing_events)
+        .service(overlay)
+        .service(get_metrics);
 }
 
 /// Creates a simple server that parses the http request from traktor to our
 /// state
-pub async fn create_server(state: Arc<Mutex<TraktorState>>) -> std::io::Result<()> {
-    let state = web::Data::new(AppState { traktor: state });
+pub async fn create_server(
+    store: Arc<StateStore>,
+    metrics: Arc<Metrics>,
+    config: &Config,
+) -> std::io::Result<()> {
+    let recorder = match &config.record {
+        Some(path) => {
+            println!("Recording traktor events to {}", path.display());
+            Some(Arc::new(Recorder::create(path)?))
+        }
+        None => None,
+    };
+    let state = web::Data::new(AppState {
+        recorder,
+        metrics,
+        ..AppState::new(store)
+    });
+
+    // The history and now playing follow the changes in the background
+    tokio::spawn(record_history(state.store.clone(), state.history.clone()));
+    tokio::spawn(follow_now_playing(
+        state.store.clone(),
+        state.now_playing.clone(),
+        config.now_playing.clone(),
+    ));
+    if let Some(path) = &config.state_file {
+        tokio::spawn(persist_state(state.store.clone(), path.clone()));
+    }
 
     // Start HTTP server
     HttpServer::new(move || {
         //Move state into closure
         App::new()
             .app_data(state.clone())
-            .service(get_state)
-            .service(receive_fx_event)
-            .service(receive_deck_loaded_event)
-            .service(receive_deck_update_event)
+            .wrap(from_fn(record_requests))
+            .wrap(from_fn(count_events))
+            .wrap(from_fn(check_protocol))
+            .configure(routes)
     })
-    .bind(("127.0.0.1", 8080))?
+    .bind((config.host, config.port))?
     .run()
     .await
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::test_server::TestServer;
+    use super::{Channel, DeckID, TraktorState};
+    use actix_web::http::StatusCode;
+
+    #[test]
+    fn test_shown_decks() {
+        let shown = |state: &TraktorState| -> Vec<DeckID> {
+            DeckID::iter()
+                .filter(|id| state.is_deck_shown(id))
+                .cloned()
+                .collect()
+        };
+        let mut state = TraktorState::default();
+        assert_eq!(shown(&state), vec![DeckID::A, DeckID::B]);
+
+        // Without a focus the master deck picks the pair
+        state.master_clock.deck = Some(DeckID::D);
+        assert_eq!(shown(&state), vec![DeckID::C, DeckID::D]);
+
+        state.focused_deck = Some(DeckID::B);
+        assert_eq!(shown(&state), vec![DeckID::A, DeckID::B]);
+    }
+
+    #[test]
+    fn test_channel_deck_id() {
+        assert_eq!(Channel::new(1).deck_id(), Some(DeckID::A));
+        assert_eq!(Channel::new(4).deck_id(), Some(DeckID::D));
+        assert_eq!(Channel::new(0).deck_id(), None);
+        assert_eq!(Channel::new(5).deck_id(), None);
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_route() {
+        let server = TestServer::new();
+        assert_eq!(
+            server.post("/updateMixer/1", r#"{"volume":1}"#).await,
+            StatusCode::NOT_FOUND
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    get,
+    http::Method,
+    middleware::Next,
+    web, HttpResponse,
+};
+
+use crate::traktor::AppState;
+
+/// Middleware counting the posts from traktor per endpoint
+pub async fn count_events(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if req.method() != Method::POST {
+        return next.call(req).await;
+    }
+
+    let res = next.call(req).await?;
+    if let Some(data) = res.request().app_data::<web::Data<AppState>>() {
+        // Unknown routes share one label, their paths are arbitrary
+        let endpoint = res
+            .request()
+            .match_pattern()
+            .unwrap_or_else(|| "unknown".to_string());
+        data.metrics.record_event(&endpoint, res.status().as_u16());
+    }
+    Ok(res)
+}
+
+/// Frame and event metrics in the prometheus text format
+#[get("/metrics")]
+pub async fn get_metrics(data: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(data.metrics.render())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::traktor::test_server::{TestServer, DECK_LOADED};
+    use actix_web::{http::StatusCode, test::TestRequest};
+
+    #[actix_web::test]
+    async fn test_metrics() {
+        let server = TestServer::new();
+        server.post("/deckLoaded/A", DECK_LOADED).await;
+        server.post("/deckLoaded/A", "{}").await;
+        server.post("/updateMixer/1", "{}").await;
+
+        let (status, body) = server.call(TestRequest::get().uri("/metrics")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("push2_frames_total 0\n"));
+        assert!(body.contains(
+            "traktor_events_total{endpoint=\"/deckLoaded/{deck_id}\",status=\"200\"} 1\n"
+        ));
+        assert!(body.contains(
+            "traktor_events_total{endpoint=\"/deckLoaded/{deck_id}\",status=\"400\"} 1\n"
+        ));
+        assert!(body.contains("traktor_events_total{endpoint=\"unknown\",status=\"404\"} 1\n"));
+        // The get itself isn't counted
+        assert!(!body.contains("/metrics"));
+    }
+}
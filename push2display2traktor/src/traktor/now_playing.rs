@@ -0,0 +1,245 @@
+use actix_web::{get, web, web::Bytes, HttpResponse};
+use futures_util::stream;
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+use crate::config::NowPlayingFile;
+use crate::traktor::{events::sse_response, AppState, Deck, DeckID, StateStore, TraktorState};
+
+// The page served at /overlay, e.g. for an OBS browser source
+const OVERLAY_HTML: &str = include_str!("overlay.html");
+
+/// The track the audience currently hears
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct NowPlaying {
+    pub deck: DeckID,
+    pub artist: String,
+    pub title: String,
+    pub album: String,
+    pub label: String,
+    pub remixer: String,
+    pub mix: String,
+    pub genre: String,
+    pub key: String,
+    // Including the tempo adjustment
+    pub bpm: f64,
+}
+
+impl NowPlaying {
+    /// The loudest playing deck which is on air. Until traktor sent a channel,
+    /// e.g. with an external mixer, the master deck or else any playing deck
+    pub fn from_state(state: &TraktorState) -> Option<Self> {
+        let level = |deck: &Deck| {
+            state
+                .iter_all_channels()
+                .find(|c| c.is_on_air && c.deck_id().as_ref() == Some(&deck.id))
+                .map(|c| c.on_air_level)
+        };
+        let playing = || {
+            state
+                .iter_all_decks()
+                .filter(|d| d.content.is_some() && d.playback.is_playing)
+        };
+
+        let deck = if state.has_channels() {
+            playing()
+                .filter_map(|d| Some((d, level(d)?)))
+                .max_by(|(a, a_level), (b, b_level)| {
+                    a_level.total_cmp(b_level).then(
+                        state
+                            .is_master_deck(&a.id)
+                            .cmp(&state.is_master_deck(&b.id)),
+                    )
+                })
+                .map(|(d, _)| d)
+        } else {
+            playing()
+                .find(|d| state.is_master_deck(&d.id))
+                .or_else(|| playing().next())
+        }?;
+
+        let content = deck.content.as_ref()?;
+        Some(Self {
+            deck: deck.id.clone(),
+            artist: content.artist.clone(),
+            title: content.title.clone(),
+            album: content.album.clone(),
+            label: content.label.clone(),
+            remixer: content.remixer.clone(),
+            mix: content.mix.clone(),
+            genre: content.genre.clone(),
+            key: content.resulting_key.clone(),
+            bpm: content.bpm * deck.playback.tempo,
+        })
+    }
+
+    /// Replaces the {artist}, {title}, ... placeholders in the template
+    pub fn render(&self, template: &str) -> String {
+        template
+            .replace("{deck}", &format!("{:?}", self.deck))
+            .replace("{artist}", &self.artist)
+            .replace("{title}", &self.title)
+            .replace("{album}", &self.album)
+            .replace("{label}", &self.label)
+            .replace("{remixer}", &self.remixer)
+            .replace("{mix}", &self.mix)
+            .replace("{genre}", &self.genre)
+            .replace("{key}", &self.key)
+            .replace("{bpm}", &format!("{:.0}", self.bpm))
+    }
+
+    fn same_track(a: &Option<Self>, b: &Option<Self>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => a.deck == b.deck && a.artist == b.artist && a.title == b.title,
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Follows the state and publishes the now playing track whenever it
+/// changes, also writing it to the configured text files.
+///
+/// Tempo changes alone don't count as a change.
+pub async fn follow_now_playing(
+    store: Arc<StateStore>,
+    now_playing: Arc<watch::Sender<Option<NowPlaying>>>,
+    files: Vec<NowPlayingFile>,
+) {
+    let mut state = store.watch();
+    write_files(&files, &None).await;
+
+    loop {
+        let current = NowPlaying::from_state(&state.borrow_and_update());
+        if !NowPlaying::same_track(&current, &now_playing.borrow()) {
+            write_files(&files, &current).await;
+            now_playing.send_replace(current);
+        }
+
+        if state.changed().await.is_err() {
+            break;
+        }
+    }
+}
+
+// Empty if nothing is playing, so the overlay text disappears
+async fn write_files(files: &[NowPlayingFile], now_playing: &Option<NowPlaying>) {
+    for file in files {
+        let text = match now_playing {
+            Some(n) => n.render(&file.template),
+            None => String::new(),
+        };
+        if let Err(e) = tokio::fs::write(&file.path, text).await {
+            println!("Failed to write {}: {}", file.path.display(), e);
+        }
+    }
+}
+
+/// The now playing track as json, null if nothing is playing
+#[get("/now-playing")]
+pub async fn get_now_playing(data: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(&*data.now_playing.borrow())
+}
+
+/// Sends the now playing track once and again whenever it changes
+#[get("/now-playing/events")]
+pub async fn now_playing_events(data: web::Data<AppState>) -> HttpResponse {
+    let mut receiver = data.now_playing.subscribe();
+    receiver.mark_changed();
+
+    let messages = stream::unfold(receiver, |mut receiver| async move {
+        receiver.changed().await.ok()?;
+        let json = serde_json::to_string(&*receiver.borrow_and_update()).unwrap();
+        Some((Bytes::from(format!("data: {}\n\n", json)), receiver))
+    });
+    sse_response(messages)
+}
+
+#[get("/overlay")]
+pub async fn overlay() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(OVERLAY_HTML)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::traktor::{Channel, DeckContent, MasterClock, MixerKnobs};
+
+    fn load(state: &mut TraktorState, deck: usize, title: &str, is_playing: bool) {
+        let deck = &mut state.decks[deck];
+        deck.content = Some(DeckContent {
+            artist: "Artist".to_string(),
+            key: "Am".to_string(),
+            key_text: "8A".to_string(),
+            resulting_key: "Am".to_string(),
+            ..DeckContent::for_test(title, 120.0, 300.0)
+        });
+        deck.playback.is_playing = is_playing;
+        deck.playback.tempo = 1.05;
+    }
+
+    fn set_channel(state: &mut TraktorState, id: u8, on_air_level: f64) {
+        state.channels[id as usize - 1] = Channel {
+            id,
+            is_on_air: on_air_level > 0.0,
+            on_air_level,
+            mixer: MixerKnobs::default(),
+        };
+        state.has_channels = true;
+    }
+
+    fn title(state: &TraktorState) -> Option<String> {
+        NowPlaying::from_state(state).map(|n| n.title)
+    }
+
+    #[test]
+    fn test_now_playing() {
+        let mut state = TraktorState::default();
+        assert_eq!(title(&state), None);
+
+        // Loaded but stopped
+        load(&mut state, 0, "First", false);
+        assert_eq!(title(&state), None);
+
+        // Playing without mixer info
+        load(&mut state, 0, "First", true);
+        load(&mut state, 1, "Second", true);
+        assert_eq!(title(&state), Some("First".to_string()));
+        state.master_clock = MasterClock {
+            deck: Some(DeckID::B),
+            bpm: 126.0,
+        };
+        assert_eq!(title(&state), Some("Second".to_string()));
+
+        // The louder channel wins during a crossfade
+        set_channel(&mut state, 1, 0.7);
+        set_channel(&mut state, 2, 0.3);
+        assert_eq!(title(&state), Some("First".to_string()));
+        set_channel(&mut state, 1, 0.2);
+        assert_eq!(title(&state), Some("Second".to_string()));
+
+        // Prelistening doesn't count
+        set_channel(&mut state, 2, 0.0);
+        load(&mut state, 2, "Third", true);
+        assert_eq!(title(&state), Some("First".to_string()));
+
+        // Not even with all faders down
+        set_channel(&mut state, 1, 0.0);
+        assert_eq!(title(&state), None);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut state = TraktorState::default();
+        load(&mut state, 3, "Title", true);
+        let now_playing = NowPlaying::from_state(&state).unwrap();
+        assert_eq!(
+            now_playing.render("{artist} – {title} ({key}, {bpm} bpm) on {deck} {unknown}"),
+            "Artist – Title (Am, 126 bpm) on D {unknown}"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::traktor::{StateStore, TraktorState, Versions};
+
+// Often enough to lose at most a few knob turns on a crash
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Reads a state saved by `save_state` and marks it as stale.
+///
+/// Returns None if there is no saved state yet or it can't be parsed,
+/// e.g. after an update changed the format. Starting fresh is better
+/// than not starting at all.
+pub fn load_state(path: &Path) -> Option<TraktorState> {
+    let json = match std::fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            println!("Failed to read the state from {}: {}", path.display(), e);
+            return None;
+        }
+    };
+
+    match serde_json::from_str::<TraktorState>(&json) {
+        Ok(mut state) => {
+            state.mark_stale();
+            Some(state)
+        }
+        Err(e) => {
+            println!("Ignoring the state in {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Writes the state to a temporary file first and renames it, so a crash
+/// while writing never leaves a broken file behind
+pub fn save_state(path: &Path, state: &TraktorState) -> io::Result<()> {
+    let json = serde_json::to_string(state).map_err(io::Error::other)?;
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, json)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Saves the state whenever it changed, checked every few seconds
+pub async fn persist_state(store: Arc<StateStore>, path: PathBuf) {
+    let mut interval = tokio::time::interval(SAVE_INTERVAL);
+    let mut saved = Versions::default();
+
+    loop {
+        interval.tick().await;
+
+        let state = store.snapshot();
+        if *state.versions() == saved {
+            continue;
+        }
+        match save_state(&path, &state) {
+            Ok(()) => saved = *state.versions(),
+            Err(e) => println!("Failed to save the state to {}: {}", path.display(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::traktor::DeckID;
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("state-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        assert!(load_state(&path).is_none());
+
+        let mut state = TraktorState::default();
+        state.fx_units[1].knobs[2].fx_name = "Reverb".to_string();
+        state.decks[0].playback.elapsed_time = 93.5;
+        state.decks[0].playback.is_playing = true;
+        state.master_clock.deck = Some(DeckID::A);
+        state.channels[0].is_on_air = true;
+        save_state(&path, &state).unwrap();
+
+        let restored = load_state(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(restored.fx_units[1].knobs[2].fx_name, "Reverb");
+        assert!(restored.decks.iter().all(|d| d.stale));
+        assert!(restored.iter_knob_stale().all(|stale| stale));
+
+        // Waits at the saved position instead of running on
+        let deck = &restored.decks[0];
+        assert!(deck.playback.is_playing);
+        let later = std::time::Instant::now() + Duration::from_secs(10);
+        assert_eq!(deck.position_at(later), 93.5);
+
+        // Not known until traktor sends them
+        assert_eq!(restored.master_clock().deck, None);
+        assert!(!restored.is_deck_on_air(&DeckID::A));
+    }
+
+    #[test]
+    fn test_load_broken_file() {
+        let path = std::env::temp_dir().join(format!("state-broken-{}.json", std::process::id()));
+        std::fs::write(&path, "{\"decks\": 1}").unwrap();
+        assert!(load_state(&path).is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+use std::time::Instant;
+
+// Differences larger than this are seeks or cue jumps
+// and are applied immediately instead of smoothed
+const SNAP_THRESHOLD: f64 = 0.25;
+// Time in seconds over which a small correction is faded out,
+// stretched at low rates so the position never moves backwards
+const CORRECTION_TIME: f64 = 0.5;
+
+/// Extrapolates the playhead position of a deck between the
+/// elapsed time updates, which traktor only sends once per second.
+///
+/// Every update sets a new anchor (position + time of arrival), the
+/// position is then advanced by the playback rate until the next one.
+#[derive(Clone, Debug)]
+pub struct Playhead {
+    anchor_position: f64,
+    anchor_time: Instant,
+    // Track seconds per wall clock second, 0 if paused
+    rate: f64,
+    // Offset between the extrapolated and the reported position
+    // when the anchor was set, fades out over CORRECTION_TIME
+    correction: f64,
+}
+
+impl Default for Playhead {
+    fn default() -> Self {
+        Self::new(0.0, 0.0, Instant::now())
+    }
+}
+
+impl Playhead {
+    pub fn new(position: f64, rate: f64, now: Instant) -> Self {
+        Self {
+            anchor_position: position,
+            anchor_time: now,
+            rate,
+            correction: 0.0,
+        }
+    }
+
+    /// Position in seconds at the given time
+    pub fn position_at(&self, now: Instant) -> f64 {
+        let dt = now.saturating_duration_since(self.anchor_time).as_secs_f64();
+        // Being ahead is faded out no faster than the track plays
+        let correction_time = if self.correction > 0.0 {
+            CORRECTION_TIME.max(self.correction / self.rate)
+        } else {
+            CORRECTION_TIME
+        };
+        let fade = (1.0 - dt / correction_time).max(0.0);
+        self.anchor_position + dt * self.rate + self.correction * fade
+    }
+
+    /// Sets a new anchor from a reported position.
+    ///
+    /// Small differences to the extrapolated position are smoothed while
+    /// the deck keeps playing, anything else snaps to the reported value.
+    pub fn update_at(&mut self, position: f64, rate: f64, now: Instant) {
+        let error = self.position_at(now) - position;
+        let keeps_playing = self.rate > 0.0 && rate > 0.0;
+
+        self.correction = if keeps_playing && error.abs() < SNAP_THRESHOLD {
+            error
+        } else {
+            0.0
+        };
+        self.anchor_position = position;
+        self.anchor_time = now;
+        self.rate = rate;
+    }
+
+    /// Changes the playback rate without moving the playhead
+    pub fn set_rate_at(&mut self, rate: f64, now: Instant) {
+        let position = self.position_at(now);
+        *self = Self::new(position, rate, now);
+    }
+
+    pub fn update(&mut self, position: f64, rate: f64) {
+        self.update_at(position, rate, Instant::now());
+    }
+
+    pub fn set_rate(&mut self, rate: f64) {
+        self.set_rate_at(rate, Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::time::Duration;
+
+    fn after(start: Instant, secs: f64) -> Instant {
+        start + Duration::from_secs_f64(secs)
+    }
+
+    #[test]
+    fn test_extrapolate() {
+        let start = Instant::now();
+        let playhead = Playhead::new(10.0, 1.0, start);
+
+        assert_eq!(playhead.position_at(start), 10.0);
+        assert!((playhead.position_at(after(start, 0.5)) - 10.5).abs() < 1e-9);
+
+        // Tempo scales the track time
+        let playhead = Playhead::new(10.0, 1.1, start);
+        assert!((playhead.position_at(after(start, 1.0)) - 11.1).abs() < 1e-9);
+
+        // Paused decks stay put
+        let playhead = Playhead::new(10.0, 0.0, start);
+        assert_eq!(playhead.position_at(after(start, 5.0)), 10.0);
+    }
+
+    #[test]
+    fn test_smooth_correction() {
+        let start = Instant::now();
+        let mut playhead = Playhead::new(10.0, 1.0, start);
+
+        // Update arrives a bit late, the report lags our estimate
+        let now = after(start, 1.0);
+        playhead.update_at(10.9, 1.0, now);
+
+        // No jump when the update arrives
+        assert!((playhead.position_at(now) - 11.0).abs() < 1e-9);
+        // Never moves backwards
+        assert!(playhead.position_at(after(now, 0.1)) > playhead.position_at(now));
+        // Converged onto the reported position afterwards
+        let later = after(now, CORRECTION_TIME + 0.5);
+        assert!((playhead.position_at(later) - (10.9 + CORRECTION_TIME + 0.5)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_smooth_correction_low_rate() {
+        let start = Instant::now();
+        let mut playhead = Playhead::new(10.0, 0.2, start);
+
+        // Ahead by more than the deck plays during the correction time
+        let now = after(start, 1.0);
+        playhead.update_at(10.0, 0.2, now);
+        assert!((playhead.position_at(now) - 10.2).abs() < 1e-9);
+
+        let mut last = playhead.position_at(now);
+        for i in 1..=20 {
+            let position = playhead.position_at(after(now, i as f64 * 0.1));
+            assert!(
+                position >= last,
+                "moved back to {} after {}",
+                position,
+                last
+            );
+            last = position;
+        }
+        assert!((last - 10.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_snap() {
+        let start = Instant::now();
+        let mut playhead = Playhead::new(10.0, 1.0, start);
+
+        // Jump to a cue point
+        let now = after(start, 1.0);
+        playhead.update_at(64.0, 1.0, now);
+        assert_eq!(playhead.position_at(now), 64.0);
+
+        // Pausing always snaps to the exact reported position
+        let now = after(now, 1.0);
+        playhead.update_at(64.9, 0.0, now);
+        assert_eq!(playhead.position_at(now), 64.9);
+        assert_eq!(playhead.position_at(after(now, 1.0)), 64.9);
+    }
+
+    #[test]
+    fn test_set_rate() {
+        let start = Instant::now();
+        let mut playhead = Playhead::new(0.0, 1.0, start);
+
+        let now = after(start, 2.0);
+        playhead.set_rate_at(0.5, now);
+        assert!((playhead.position_at(now) - 2.0).abs() < 1e-9);
+        assert!((playhead.position_at(after(now, 2.0)) - 3.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,240 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    http::Method,
+    middleware::Next,
+    web::{self, Bytes},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, LineWriter, Write},
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::traktor::{ApiClient, AppState, PROTOCOL_HEADER};
+
+/// A request received from traktor, one line in the session file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordedRequest {
+    // Seconds since the recording started
+    pub time: f64,
+    // Route of the handler, e.g. "/fx/{fx_unit_id}"
+    pub endpoint: String,
+    pub path: String,
+    pub params: BTreeMap<String, String>,
+    // Protocol header as sent, None for clients without one
+    pub protocol: Option<String>,
+    // Raw body, replayed as is even if it isn't valid json
+    pub body: String,
+}
+
+/// Appends every request from traktor to a jsonl file, so a set can be
+/// replayed later without traktor running
+pub struct Recorder {
+    start: Instant,
+    file: Mutex<LineWriter<File>>,
+}
+
+impl Recorder {
+    /// Appends to the file if it exists, so a restart doesn't lose
+    /// the earlier part of the set
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            start: Instant::now(),
+            file: Mutex::new(LineWriter::new(file)),
+        })
+    }
+
+    pub fn elapsed(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+
+    pub fn record(&self, request: &RecordedRequest) {
+        let line = serde_json::to_string(request).unwrap();
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = writeln!(file, "{}", line) {
+            println!("Failed to record traktor event: {}", e);
+        }
+    }
+}
+
+/// Middleware logging rejected posts with their body, so a payload the
+/// qml changed can be found, and recording all posts if the server was
+/// started with a recorder.
+///
+/// Rejected requests are recorded too, they are often the interesting ones.
+pub async fn record_requests(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if req.method() != Method::POST {
+        return next.call(req).await;
+    }
+    let recorder = req
+        .app_data::<web::Data<AppState>>()
+        .and_then(|data| data.recorder.clone());
+
+    let time = recorder.as_ref().map(|r| r.elapsed()).unwrap_or_default();
+    let protocol = req
+        .headers()
+        .get(PROTOCOL_HEADER)
+        .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned());
+
+    // Read the body and put it back for the handler
+    let body = req.extract::<Bytes>().await?;
+    req.set_payload(Payload::from(body.clone()));
+    // ApiClient.js only sends utf-8
+    let body = String::from_utf8_lossy(&body).into_owned();
+
+    // The path params are only known after routing
+    let res = next.call(req).await?;
+    let request = res.request();
+    if let Some(error) = res.response().error() {
+        println!(
+            "Rejected traktor event {}: {}, body {}",
+            request.path(),
+            error,
+            body
+        );
+    }
+
+    if let Some(recorder) = recorder {
+        recorder.record(&RecordedRequest {
+            time,
+            endpoint: request
+                .match_pattern()
+                .unwrap_or_else(|| request.path().to_string()),
+            path: request.path().to_string(),
+            params: request
+                .match_info()
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            protocol,
+            body,
+        });
+    }
+
+    Ok(res)
+}
+
+/// Reads a session file written by the recorder
+pub fn read_session(path: &Path) -> io::Result<Vec<RecordedRequest>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut requests = vec![];
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: {}", i + 1, e))
+        })?;
+        requests.push(request);
+    }
+    Ok(requests)
+}
+
+/// Seconds after the start of the replay at which each request is sent.
+///
+/// The recorder appends, so a file can hold multiple sessions, the next
+/// one continues right after the previous instead of going back in time.
+fn schedule(requests: &[RecordedRequest], speed: f64) -> Vec<f64> {
+    let mut offset = 0.0;
+    let mut last = 0.0;
+    requests
+        .iter()
+        .map(|r| {
+            if r.time + offset < last {
+                offset = last - r.time;
+            }
+            last = r.time + offset;
+            last / speed
+        })
+        .collect()
+}
+
+/// Posts a recorded session to the api with the original timing,
+/// a speed of 2.0 replays twice as fast
+pub async fn replay(path: &Path, speed: f64, client: &ApiClient) -> io::Result<()> {
+    let requests = read_session(path)?;
+    let times = schedule(&requests, speed);
+    println!(
+        "Replaying {} requests over {:.1}s",
+        requests.len(),
+        times.last().unwrap_or(&0.0)
+    );
+
+    let start = tokio::time::Instant::now();
+    for (request, time) in requests.iter().zip(times) {
+        tokio::time::sleep_until(start + Duration::from_secs_f64(time)).await;
+        let status = client
+            .post_as(&request.path, &request.body, request.protocol.as_deref())
+            .await?;
+        if status != 200 {
+            println!("{} was answered with {}", request.path, status);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::traktor::test_server::TestServer;
+    use crate::traktor::PROTOCOL_VERSION;
+    use actix_web::{http::StatusCode, test::TestRequest};
+    use std::sync::Arc;
+
+    fn request(time: f64) -> RecordedRequest {
+        RecordedRequest {
+            time,
+            endpoint: "/updateMasterClock".to_string(),
+            path: "/updateMasterClock".to_string(),
+            params: BTreeMap::new(),
+            protocol: Some("4".to_string()),
+            body: r#"{"deck":null,"bpm":120.0}"#.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_schedule() {
+        let requests: Vec<_> = [0.0, 1.0, 3.0, 0.5, 1.5].map(request).into();
+        assert_eq!(schedule(&requests, 1.0), vec![0.0, 1.0, 3.0, 3.0, 4.0]);
+        assert_eq!(schedule(&requests, 2.0), vec![0.0, 0.5, 1.5, 1.5, 2.0]);
+    }
+
+    #[actix_web::test]
+    async fn test_record_requests() {
+        let path = std::env::temp_dir().join(format!("recorder-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let server = TestServer {
+            recorder: Some(Arc::new(Recorder::create(&path).unwrap())),
+            ..TestServer::new()
+        };
+
+        let body = r#"{"Knob": {"objectName": "", "path": "app.traktor.fx.2.knobs.1", "value": 0.75, "description": "float", "enabled": true, "valueRange": {"objectName": "", "min": 0, "max": 1, "def": 0, "steps": 0, "type": "Continuous", "isFull": false, "isContinuous": true, "isDiscrete": false}, "valuesDescription": []}}"#;
+        assert!(server.post("/fx/2", body).await.is_success());
+        // Rejected and without a protocol header
+        let req = TestRequest::post().uri("/fx/2").set_payload("not json");
+        assert_eq!(server.call(req).await.0, StatusCode::BAD_REQUEST);
+
+        let requests = read_session(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].endpoint, "/fx/{fx_unit_id}");
+        assert_eq!(requests[0].path, "/fx/2");
+        assert_eq!(requests[0].params["fx_unit_id"], "2");
+        assert_eq!(requests[0].protocol, Some(PROTOCOL_VERSION.to_string()));
+        assert_eq!(requests[0].body, body);
+        assert_eq!(requests[1].protocol, None);
+        assert_eq!(requests[1].body, "not json");
+    }
+}
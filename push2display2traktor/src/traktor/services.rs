@@ -1,12 +1,17 @@
-use crate::traktor::{AppState, DeckContent, DeckID, FxUnitType};
+use crate::traktor::{
+    error::ApiError,
+    keys::{Key, KeyNotation},
+    AppState, Deck, DeckContent, DeckID, FxUnitType, Hotcue, HotcueType, LoopState, MixerKnobs,
+    PlaybackState, Playhead, Section, StateEvent, CHANNELS, FX_UNITS, HOTCUES, KNOBS_PER_FX_UNIT,
+};
 use actix_web::{get, post, web, HttpResponse};
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use std::time::Instant;
 
 // Very simple get function to view the state
 #[get("/state")]
 pub async fn get_state(data: web::Data<AppState>) -> HttpResponse {
-    let data = data.traktor.lock().await;
-    HttpResponse::Ok().json(data.clone())
+    HttpResponse::Ok().json(data.store.snapshot())
 }
 
 #[post("/deckLoaded/{deck_id}")]
@@ -14,36 +19,37 @@ async fn receive_deck_loaded_event(
     deck_id: web::Path<String>,
     event: web::Json<DeckLoaded>,
     data: web::Data<AppState>,
-) -> HttpResponse {
-
-    let c = if let Some(c) = deck_id.chars().next() {
-        c
-    } else {
-        return HttpResponse::Ok().finish();
-    };
+) -> Result<HttpResponse, ApiError> {
+    let deck_id = parse_deck_id(&deck_id)?;
+    // Update all values, the load event also carries the current
+    // transport state so we don't have to wait for the next update
+    let event = event.into_inner();
+    data.store.update(Section::Decks, |state| {
+        let deck = state.decks.iter_mut().find(|d| d.id == deck_id)?;
 
-    let deck_id = if let Some(d) = DeckID::from_char(c) {
-        d
-    } else {
-        return HttpResponse::Ok().finish();
-    };
+        let playback = PlaybackState::from(&event);
+        let content: DeckContent = event.into();
+        deck.playhead = Playhead::new(playback.elapsed_time, playback.rate(), Instant::now());
+        deck.playback = playback.clone();
+        deck.content = Some(content.clone());
+        deck.stale = false;
 
-    {
-        let mut state = data.traktor.lock().await;
+        Some(StateEvent::DeckLoaded {
+            deck: deck_id,
+            content: Box::new(content),
+            playback,
+        })
+    });
 
-        // find deck
-        let deck_idx = if let Some(d) = state.decks.iter().position(|d| d.id == deck_id) {
-            d
-        } else {
-            return HttpResponse::Ok().finish();
-        };
-        let deck = &mut (*state).decks[deck_idx];
+    Ok(HttpResponse::Ok().finish())
+}
 
-        // Update all values
-        deck.content = Some(event.into_inner().into());
+fn parse_deck_id(deck_id: &str) -> Result<DeckID, ApiError> {
+    let mut chars = deck_id.chars();
+    match (chars.next().and_then(DeckID::from_char), chars.next()) {
+        (Some(d), None) => Ok(d),
+        _ => Err(ApiError::Deck(deck_id.to_string())),
     }
-
-    HttpResponse::Ok().finish()
 }
 
 #[post("/updateDeck/{deck_id}")]
@@ -51,47 +57,147 @@ async fn receive_deck_update_event(
     deck_id: web::Path<String>,
     event: web::Json<DeckUpdate>,
     data: web::Data<AppState>,
-) -> HttpResponse {
+) -> Result<HttpResponse, ApiError> {
+    let deck_id = parse_deck_id(&deck_id)?;
 
-    let c = if let Some(c) = deck_id.chars().next() {
-        c
-    } else {
-        return HttpResponse::Ok().finish();
-    };
+    // Update all values
+    let event = event.into_inner();
+    data.store.update(Section::Decks, |state| {
+        let deck = state.decks.iter_mut().find(|d| d.id == deck_id)?;
+        Some(apply_deck_update(deck, event))
+    });
 
-    let deck_id = if let Some(d) = DeckID::from_char(c) {
-        d
-    } else {
-        return HttpResponse::Ok().finish();
-    };
-
-
-    {
-        let mut state = data.traktor.lock().await;
+    Ok(HttpResponse::Ok().finish())
+}
 
-        // find deck
-        let deck_idx = if let Some(d) = state.decks.iter().position(|d| d.id == deck_id) {
-            d
-        } else {
-            return HttpResponse::Ok().finish();
-        };
-        let deck = &mut (*state).decks[deck_idx];
-
-        // Update all values
-        match event.into_inner() {
-            DeckUpdate::ResultingKey { resulting_key } => {
-                if let Some(content) = &mut deck.content {
-                    content.resulting_key = resulting_key;
-                }
+/// Applies the update and returns the resulting change
+fn apply_deck_update(deck: &mut Deck, update: DeckUpdate) -> StateEvent {
+    // Any update shows the restored deck is still there
+    deck.stale = false;
+    let playback = &mut deck.playback;
+    match update {
+        DeckUpdate::IsPlaying {
+            is_playing,
+            elapsed_time,
+            next_cue_pos,
+        } => {
+            playback.is_playing = is_playing;
+            playback.elapsed_time = elapsed_time;
+            playback.next_cue_pos = next_cue_pos;
+            deck.playhead.update(elapsed_time, playback.rate());
+        }
+        DeckUpdate::IsSynced { is_synced } => playback.is_synced = is_synced,
+        DeckUpdate::IsKeyLockOn { is_key_lock_on } => playback.is_key_lock_on = is_key_lock_on,
+        DeckUpdate::Tempo { tempo } => {
+            playback.tempo = tempo;
+            deck.playhead.set_rate(playback.rate());
+        }
+        DeckUpdate::ResultingKey { resulting_key } => {
+            if let Some(content) = &mut deck.content {
+                content.resulting_key = resulting_key.clone();
             }
-            // For now do nothing
-            _ => (),
+            return StateEvent::KeyChanged {
+                deck: deck.id.clone(),
+                resulting_key,
+            };
+        }
+        DeckUpdate::ElapsedTime {
+            elapsed_time,
+            next_cue_pos,
+        } => {
+            playback.elapsed_time = elapsed_time;
+            playback.next_cue_pos = next_cue_pos;
+            deck.playhead.update(elapsed_time, playback.rate());
+        }
+        DeckUpdate::Loop {
+            is_active,
+            size,
+            beatjump_size,
+        } => {
+            deck.r#loop = LoopState {
+                is_active,
+                size,
+                beatjump_size,
+            };
+            return StateEvent::LoopChanged {
+                deck: deck.id.clone(),
+                r#loop: deck.r#loop.clone(),
+            };
         }
     }
 
+    StateEvent::DeckUpdated {
+        deck: deck.id.clone(),
+        playback: deck.playback.clone(),
+    }
+}
+
+#[post("/updateHotcue/{deck_id}/{hotcue_id}")]
+async fn receive_hotcue_event(
+    path: web::Path<(String, u8)>,
+    event: web::Json<Option<HotcueUpdate>>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (deck_id, hotcue_id) = path.into_inner();
+    let deck_id = parse_deck_id(&deck_id)?;
+    if !(1..=HOTCUES).contains(&hotcue_id) {
+        return Err(ApiError::Hotcue(hotcue_id));
+    }
+
+    let hotcue = match event.into_inner() {
+        Some(update) => Some(Hotcue {
+            r#type: HotcueType::from_traktor(update.r#type)
+                .ok_or(ApiError::HotcueType(update.r#type))?,
+            position: update.position,
+            length: update.length,
+            name: update.name,
+        }),
+        None => None,
+    };
+
+    data.store.update(Section::Decks, |state| {
+        let deck = state.decks.iter_mut().find(|d| d.id == deck_id)?;
+        deck.hotcues[hotcue_id as usize - 1] = hotcue.clone();
+
+        Some(StateEvent::HotcueChanged {
+            deck: deck_id,
+            hotcue: hotcue_id,
+            content: hotcue,
+        })
+    });
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+// ApiHotcue.qml sends null for pads without a hotcue
+#[derive(Debug, Deserialize)]
+struct HotcueUpdate {
+    r#type: u8,
+    position: f64,
+    length: f64,
+    name: String,
+}
+
+#[post("/updateFocus")]
+async fn receive_focus_event(
+    event: web::Json<FocusUpdate>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let deck = event.into_inner().deck;
+
+    data.store.update(Section::Decks, |state| {
+        state.focused_deck = Some(deck.clone());
+        Some(StateEvent::FocusChanged { deck })
+    });
+
     HttpResponse::Ok().finish()
 }
 
+#[derive(Debug, Deserialize)]
+struct FocusUpdate {
+    deck: DeckID,
+}
+
 #[derive(Debug, Deserialize)]
 struct DeckLoaded {
     #[serde(rename = "filePath")]
@@ -100,42 +206,102 @@ struct DeckLoaded {
     artist: String,
     album: String,
     genre: String,
+    comment: String,
+    comment2: String,
     label: String,
+    mix: String,
+    remixer: String,
     key: String,
-    // after adjust
-    #[serde(rename = "resultingKey")]
-    resulting_key: String,
+    #[serde(rename = "keyText")]
+    key_text: String,
+    #[serde(rename = "gridOffset")]
+    grid_offset: f64,
     #[serde(rename = "trackLength")]
     track_length: f64,
+    #[serde(rename = "elapsedTime")]
+    elapsed_time: f64,
+    #[serde(rename = "nextCuePos")]
+    next_cue_pos: Option<f64>,
+    bpm: f64,
+    tempo: f64,
+    // after adjust
+    #[serde(rename = "resultingKey", deserialize_with = "deserialize_key")]
+    resulting_key: String,
+    #[serde(rename = "isPlaying")]
+    is_playing: bool,
+    #[serde(rename = "isSynced")]
+    is_synced: bool,
+    #[serde(rename = "isKeyLockOn")]
+    is_key_lock_on: bool,
 }
 
-#[derive(Debug, Deserialize)]
+// The variants are tried in order, IsPlaying has to come
+// before ElapsedTime as it is a superset of it.
+// nextCuePos is null if the track has no cue point ahead
+#[derive(Debug, Deserialize, PartialEq)]
 #[serde(untagged)]
 enum DeckUpdate {
     IsPlaying {
+        #[serde(rename = "isPlaying")]
         is_playing: bool,
+        #[serde(rename = "elapsedTime")]
         elapsed_time: f64,
-        next_cue_pos: f64,
+        #[serde(rename = "nextCuePos")]
+        next_cue_pos: Option<f64>,
     },
     IsSynced {
+        #[serde(rename = "isSynced")]
         is_synced: bool,
     },
     IsKeyLockOn {
+        #[serde(rename = "isKeyLockOn")]
         is_key_lock_on: bool,
     },
     Tempo {
         tempo: f64,
     },
     ResultingKey {
-        #[serde(rename = "resultingKey")]
+        #[serde(rename = "resultingKey", deserialize_with = "deserialize_key")]
         resulting_key: String,
     },
     ElapsedTime {
+        #[serde(rename = "elapsedTime")]
         elapsed_time: f64,
-        next_cue_pos: f64,
+        #[serde(rename = "nextCuePos")]
+        next_cue_pos: Option<f64>,
+    },
+    // Always sent together so the variant has a required field,
+    // the sizes are null for anything but a number of beats
+    Loop {
+        #[serde(rename = "isLoopActive")]
+        is_active: bool,
+        #[serde(rename = "loopSize")]
+        size: Option<f64>,
+        #[serde(rename = "beatjumpSize")]
+        beatjump_size: Option<f64>,
     },
 }
 
+/// track.key.resulting.precise is traktor's key index, a number with
+/// decimals. It's kept as the musical key like the other key fields,
+/// texts are taken as they are
+fn deserialize_key<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum IndexOrText {
+        Index(f64),
+        Text(String),
+    }
+
+    Ok(match IndexOrText::deserialize(deserializer)? {
+        IndexOrText::Index(index) => {
+            let text = index.to_string();
+            Key::parse(&text).map_or(text, |key| key.format(KeyNotation::Musical))
+        }
+        IndexOrText::Text(text) => text,
+    })
+}
+
 impl Into<DeckContent> for DeckLoaded {
     fn into(self) -> DeckContent {
         DeckContent {
@@ -144,27 +310,152 @@ impl Into<DeckContent> for DeckLoaded {
             artist: self.artist,
             album: self.album,
             genre: self.genre,
+            comment: self.comment,
+            comment2: self.comment2,
             label: self.label,
+            mix: self.mix,
+            remixer: self.remixer,
             key: self.key,
+            key_text: self.key_text,
             resulting_key: self.resulting_key,
+            grid_offset: self.grid_offset,
+            bpm: self.bpm,
             length: self.track_length,
         }
     }
 }
 
+impl From<&DeckLoaded> for PlaybackState {
+    fn from(event: &DeckLoaded) -> Self {
+        PlaybackState {
+            is_playing: event.is_playing,
+            is_synced: event.is_synced,
+            is_key_lock_on: event.is_key_lock_on,
+            tempo: event.tempo,
+            elapsed_time: event.elapsed_time,
+            next_cue_pos: event.next_cue_pos,
+        }
+    }
+}
+
+#[post("/updateChannel/{channel_id}")]
+async fn receive_channel_update_event(
+    channel_id: web::Path<u8>,
+    event: web::Json<ChannelUpdate>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let channel_id = channel_id.into_inner();
+    if !(1..=CHANNELS).contains(&channel_id) {
+        return Err(ApiError::Channel(channel_id));
+    }
+
+    let event = event.into_inner();
+    data.store.update(Section::Channels, |state| {
+        state.has_channels = true;
+        // find channel
+        let channel = state.channels.iter_mut().find(|c| c.id == channel_id)?;
+
+        match event {
+            ChannelUpdate::IsOnAir { is_on_air } => channel.is_on_air = is_on_air,
+            ChannelUpdate::OnAirLevel { on_air_level } => channel.on_air_level = on_air_level,
+            ChannelUpdate::Mixer(mixer) => channel.mixer = mixer.into(),
+        }
+
+        Some(StateEvent::ChannelChanged {
+            channel: channel.clone(),
+        })
+    });
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+// ApiChannel.qml sends either the on air flag, the (debounced)
+// on air level or all mixer knobs at once, never a mix of them
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ChannelUpdate {
+    IsOnAir {
+        #[serde(rename = "isOnAir")]
+        is_on_air: bool,
+    },
+    OnAirLevel {
+        #[serde(rename = "onAirLevel")]
+        on_air_level: f64,
+    },
+    Mixer(MixerUpdate),
+}
+
+#[derive(Debug, Deserialize)]
+struct MixerUpdate {
+    gain: f64,
+    #[serde(rename = "eqHigh")]
+    eq_high: f64,
+    #[serde(rename = "eqMid")]
+    eq_mid: f64,
+    #[serde(rename = "eqLow")]
+    eq_low: f64,
+    filter: f64,
+    #[serde(rename = "isFilterOn")]
+    is_filter_on: bool,
+}
+
+impl From<MixerUpdate> for MixerKnobs {
+    fn from(update: MixerUpdate) -> Self {
+        Self {
+            gain: update.gain,
+            eq_high: update.eq_high,
+            eq_mid: update.eq_mid,
+            eq_low: update.eq_low,
+            filter: update.filter,
+            is_filter_on: update.is_filter_on,
+        }
+    }
+}
+
+#[post("/updateMasterClock")]
+async fn receive_master_clock_event(
+    event: web::Json<MasterClockUpdate>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let event = event.into_inner();
+
+    data.store.update(Section::MasterClock, |state| {
+        state.master_clock.deck = event.deck;
+        state.master_clock.bpm = event.bpm;
+
+        Some(StateEvent::MasterClockChanged {
+            master_clock: state.master_clock.clone(),
+        })
+    });
+
+    HttpResponse::Ok().finish()
+}
+
+// Deck letter is null if the internal clock is master
+#[derive(Debug, Deserialize)]
+struct MasterClockUpdate {
+    deck: Option<DeckID>,
+    bpm: f64,
+}
+
 #[post("/fx/{fx_unit_id}")]
 async fn receive_fx_event(
     fx_unit_id: web::Path<usize>,
     event: web::Json<FxEvent>,
     data: web::Data<AppState>,
-) -> HttpResponse {
+) -> Result<HttpResponse, ApiError> {
+    let fx_unit_id = fx_unit_id.into_inner();
+    if !(1..=FX_UNITS as usize).contains(&fx_unit_id) {
+        return Err(ApiError::FxUnit(fx_unit_id));
+    }
+
     let e: FxChangeEvent = match event.into_inner() {
         FxEvent::Type(e) => FxChangeEvent {
             fx_type: Some(e.description),
             ..Default::default()
         },
         FxEvent::Select(e) => FxChangeEvent {
-            fx_id: fx_id_from_traktor_path(&e.path),
+            fx_id: Some(fx_id_from_traktor_path(&e.path)?),
             name: Some(e.description),
             ..Default::default()
         },
@@ -176,17 +467,17 @@ async fn receive_fx_event(
             ..Default::default()
         },
         FxEvent::Knob(e) => FxChangeEvent {
-            fx_id: fx_id_from_traktor_path(&e.path),
+            fx_id: Some(fx_id_from_traktor_path(&e.path)?),
             value: Some(e.value),
             ..Default::default()
         },
         FxEvent::Name(e) => FxChangeEvent {
-            fx_id: fx_id_from_traktor_path(&e.path),
+            fx_id: Some(fx_id_from_traktor_path(&e.path)?),
             name: Some(e.value),
             ..Default::default()
         },
         FxEvent::Param(e) => FxChangeEvent {
-            fx_id: fx_id_from_traktor_path(&e.path),
+            fx_id: Some(fx_id_from_traktor_path(&e.path)?),
             value: Some(e.value),
             ..Default::default()
         },
@@ -195,9 +486,10 @@ async fn receive_fx_event(
     //println!("Got event {:#?}",e);
 
     // Update state
-    {
-        let mut state = data.traktor.lock().await;
-        let fx_unit = &mut (*state).fx_units[fx_unit_id.into_inner() - 1];
+    data.store.update(Section::FxUnits, |state| {
+        let mut changes = vec![];
+        let fx_unit = &mut state.fx_units[fx_unit_id - 1];
+        fx_unit.stale = false;
 
         if let Some(fx_type) = e.fx_type {
             fx_unit.r#type = match fx_type.as_str() {
@@ -205,6 +497,10 @@ async fn receive_fx_event(
                 "Single" => FxUnitType::Single,
                 _ => FxUnitType::UNK,
             };
+            changes.push(StateEvent::FxUnitChanged {
+                fx_unit: fx_unit.id,
+                unit_type: fx_unit.r#type.clone(),
+            });
         };
 
         if let Some(fx_id) = e.fx_id {
@@ -219,27 +515,28 @@ async fn receive_fx_event(
             if let Some(n) = e.name {
                 knob.fx_name = n;
             }
+            changes.push(StateEvent::KnobChanged {
+                fx_unit: fx_unit.id,
+                knob: knob.clone(),
+            });
         }
-    }
+        changes
+    });
 
-    HttpResponse::Ok().finish()
+    Ok(HttpResponse::Ok().finish())
 }
 
-fn fx_id_from_traktor_path(path: &str) -> Option<u8> {
+fn fx_id_from_traktor_path(path: &str) -> Result<u8, ApiError> {
     // Split the string by dots
     let parts: Vec<&str> = path.split('.').collect();
 
-    // Check if the split produced enough parts to avoid index out of bounds
-    // ignore app.traktor.fx.[n]
-    if parts.len() > 5 {
-        // Is always the 6th pos
-        if let Ok(number) = parts[5].parse::<u8>() {
-            return Some(number);
-        }
-    }
-
-    // Return None if parsing failed or input was invalid
-    None
+    // ignore app.traktor.fx.[n], the knob is always the 6th pos.
+    // 0 is the dry/wet knob, which has no number in the path
+    parts
+        .get(5)
+        .and_then(|part| part.parse::<u8>().ok())
+        .filter(|number| (1..KNOBS_PER_FX_UNIT).contains(number))
+        .ok_or_else(|| ApiError::Knob(path.to_string()))
 }
 
 #[derive(Deserialize)]
@@ -306,3 +603,721 @@ struct FxChangeEvent {
     value_description: Option<String>,
     name: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::traktor::test_server::*;
+    use crate::traktor::{FxUnitType, HotcueType, LoopState, StateStore, TraktorState};
+    use actix_web::{http::StatusCode, test::TestRequest};
+    use std::sync::Arc;
+
+    fn knob_event(path: &str) -> String {
+        format!(
+            r#"{{"Knob": {{"objectName": "", "path": "{}", "value": 0.25, "description": "float", "enabled": true, "valueRange": {{"objectName": "", "min": 0, "max": 1, "def": 0, "steps": 0, "type": "Continuous", "isFull": false, "isContinuous": true, "isDiscrete": false}}, "valuesDescription": []}}}}"#,
+            path
+        )
+    }
+
+    fn parse(json: &str) -> DeckUpdate {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_parse_is_playing() {
+        // propIsPlaying.onValueChanged
+        let update = parse(r#"{"elapsedTime":12.5,"nextCuePos":null,"isPlaying":true}"#);
+        assert_eq!(
+            update,
+            DeckUpdate::IsPlaying {
+                is_playing: true,
+                elapsed_time: 12.5,
+                next_cue_pos: None,
+            }
+        );
+
+        let update = parse(r#"{"elapsedTime":80.25,"nextCuePos":96.0,"isPlaying":false}"#);
+        assert_eq!(
+            update,
+            DeckUpdate::IsPlaying {
+                is_playing: false,
+                elapsed_time: 80.25,
+                next_cue_pos: Some(96.0),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_elapsed_time() {
+        // 1 second timer while playing
+        let update = parse(r#"{"elapsedTime":13.5,"nextCuePos":32.0}"#);
+        assert_eq!(
+            update,
+            DeckUpdate::ElapsedTime {
+                elapsed_time: 13.5,
+                next_cue_pos: Some(32.0),
+            }
+        );
+
+        let update = parse(r#"{"elapsedTime":13.5,"nextCuePos":null}"#);
+        assert_eq!(
+            update,
+            DeckUpdate::ElapsedTime {
+                elapsed_time: 13.5,
+                next_cue_pos: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_flags() {
+        assert_eq!(
+            parse(r#"{"isSynced":true}"#),
+            DeckUpdate::IsSynced { is_synced: true }
+        );
+        assert_eq!(
+            parse(r#"{"isKeyLockOn":false}"#),
+            DeckUpdate::IsKeyLockOn {
+                is_key_lock_on: false
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_tempo_and_key() {
+        assert_eq!(
+            parse(r#"{"tempo":1.025}"#),
+            DeckUpdate::Tempo { tempo: 1.025 }
+        );
+        assert_eq!(
+            parse(r#"{"resultingKey":"Am"}"#),
+            DeckUpdate::ResultingKey {
+                resulting_key: "Am".to_string()
+            }
+        );
+        // The key index traktor sends, rounded to the closest key
+        assert_eq!(
+            parse(r#"{"resultingKey":21.0399}"#),
+            DeckUpdate::ResultingKey {
+                resulting_key: "Am".to_string()
+            }
+        );
+        assert_eq!(
+            parse(r#"{"resultingKey":-1}"#),
+            DeckUpdate::ResultingKey {
+                resulting_key: "-1".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_loop() {
+        assert_eq!(
+            parse(r#"{"isLoopActive":true,"loopSize":0.25,"beatjumpSize":null}"#),
+            DeckUpdate::Loop {
+                is_active: true,
+                size: Some(0.25),
+                beatjump_size: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_deck_loaded() {
+        // deckLoadedTimer
+        let event: DeckLoaded = serde_json::from_str(
+            r#"{
+                "filePath": "/Volumes/Music/Track.mp3",
+                "title": "Title",
+                "artist": "Artist",
+                "album": "Album",
+                "genre": "Techno",
+                "comment": "Comment",
+                "comment2": "Comment 2",
+                "label": "Label",
+                "mix": "Original Mix",
+                "remixer": "Remixer",
+                "key": "Am",
+                "keyText": "8A",
+                "gridOffset": 0.125,
+                "trackLength": 360.5,
+                "elapsedTime": 0,
+                "nextCuePos": null,
+                "bpm": 128,
+                "tempo": 1.02,
+                "resultingKey": "Am",
+                "isPlaying": false,
+                "isSynced": true,
+                "isKeyLockOn": true
+            }"#,
+        )
+        .unwrap();
+
+        let playback = PlaybackState::from(&event);
+        assert_eq!(
+            playback,
+            PlaybackState {
+                is_playing: false,
+                is_synced: true,
+                is_key_lock_on: true,
+                tempo: 1.02,
+                elapsed_time: 0.0,
+                next_cue_pos: None,
+            }
+        );
+
+        let content: DeckContent = event.into();
+        assert_eq!(content.comment2, "Comment 2");
+        assert_eq!(content.remixer, "Remixer");
+        assert_eq!(content.key_text, "8A");
+        assert_eq!(content.grid_offset, 0.125);
+        assert_eq!(content.bpm, 128.0);
+        assert_eq!(content.length, 360.5);
+    }
+
+    #[test]
+    fn test_apply_deck_update() {
+        let mut deck = Deck::new(DeckID::A);
+
+        apply_deck_update(
+            &mut deck,
+            parse(r#"{"elapsedTime":1.0,"nextCuePos":16.0,"isPlaying":true}"#),
+        );
+        apply_deck_update(&mut deck, parse(r#"{"isSynced":true}"#));
+        apply_deck_update(&mut deck, parse(r#"{"isKeyLockOn":true}"#));
+        apply_deck_update(&mut deck, parse(r#"{"tempo":0.98}"#));
+        apply_deck_update(&mut deck, parse(r#"{"elapsedTime":2.0,"nextCuePos":null}"#));
+
+        assert!(deck.playback.is_playing);
+        assert!(deck.playback.is_synced);
+        assert!(deck.playback.is_key_lock_on);
+        assert_eq!(deck.playback.tempo, 0.98);
+        assert_eq!(deck.playback.elapsed_time, 2.0);
+        assert_eq!(deck.playback.next_cue_pos, None);
+
+        // Key changes are dropped while nothing is loaded
+        apply_deck_update(&mut deck, parse(r#"{"resultingKey":"Am"}"#));
+        assert!(deck.content.is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_fx_valid() {
+        let server = TestServer::new();
+        let status = server
+            .post("/fx/1", &knob_event("app.traktor.fx.1.knobs.3"))
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        let status = server
+            .post("/fx/4", &knob_event("app.traktor.fx.4.knobs.1"))
+            .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_fx_unknown_unit() {
+        let server = TestServer::new();
+        for uri in ["/fx/0", "/fx/5", "/fx/9"] {
+            let req = api_request(uri, &knob_event("app.traktor.fx.1.knobs.1"));
+            let (status, body) = server.call(req).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert!(body.starts_with(r#"{"error":"Unknown fx unit"#), "{}", body);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_fx_unknown_knob() {
+        let server = TestServer::new();
+        for path in [
+            "app.traktor.fx.1.knobs.0",
+            "app.traktor.fx.1.knobs.4",
+            "app.traktor.fx.1.knobs.7",
+            "app.traktor.fx.1.knobs.300",
+            "app.traktor.fx.1.knobs.x",
+            "app.traktor.fx.1.knobs",
+        ] {
+            let (status, body) = server.call(api_request("/fx/1", &knob_event(path))).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", path);
+            assert!(body.starts_with(r#"{"error":"Unknown knob in"#), "{}", body);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_deck_and_channel() {
+        let server = TestServer::new();
+        for (uri, body) in [
+            ("/updateDeck/E", r#"{"isSynced":true}"#),
+            ("/updateDeck/AB", r#"{"isSynced":true}"#),
+            ("/updateHotcue/Z/3", "null"),
+            ("/updateHotcue/A/x", "null"),
+            ("/updateChannel/0", r#"{"isOnAir":true}"#),
+            ("/updateChannel/5", r#"{"isOnAir":true}"#),
+        ] {
+            assert_eq!(
+                server.post(uri, body).await,
+                StatusCode::BAD_REQUEST,
+                "{}",
+                uri
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_get_state() {
+        let server = TestServer::new();
+        let (status, body) = server.call(TestRequest::get().uri("/state")).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            json["fx_units"].as_array().unwrap().len(),
+            FX_UNITS as usize
+        );
+        assert_eq!(json["decks"].as_array().unwrap().len(), 4);
+        assert_eq!(
+            json["channels"].as_array().unwrap().len(),
+            CHANNELS as usize
+        );
+        assert_eq!(json["master_clock"]["deck"], serde_json::Value::Null);
+    }
+
+    #[actix_web::test]
+    async fn test_deck_loaded() {
+        let server = TestServer::new();
+        assert_eq!(
+            server.post("/deckLoaded/B", DECK_LOADED).await,
+            StatusCode::OK
+        );
+
+        let deck = server.deck(DeckID::B);
+        let content = deck.content.unwrap();
+        assert_eq!(content.title, "Title");
+        assert_eq!(content.file_path, "C:\\Music\\Artist - Title.mp3");
+        assert_eq!(content.key_text, "8A");
+        assert_eq!(content.resulting_key, "Am");
+        assert_eq!(content.bpm, 127.99);
+        assert_eq!(content.length, 412.2);
+        assert_eq!(deck.playback, PlaybackState::default());
+
+        // Only the addressed deck is loaded
+        assert!(server.deck(DeckID::A).content.is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_deck_loaded_lowercase_deck() {
+        let server = TestServer::new();
+        assert_eq!(
+            server.post("/deckLoaded/c", DECK_LOADED).await,
+            StatusCode::OK
+        );
+        assert!(server.deck(DeckID::C).content.is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_deck_loaded_missing_field() {
+        let server = TestServer::new();
+        let body = DECK_LOADED.replace(r#""isKeyLockOn":false"#, r#""unknown":false"#);
+        assert_eq!(
+            server.post("/deckLoaded/A", &body).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert!(server.deck(DeckID::A).content.is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_update_deck() {
+        let server = TestServer::new();
+        server.post("/deckLoaded/A", DECK_LOADED).await;
+
+        for body in [
+            r#"{"isSynced":true}"#,
+            r#"{"isKeyLockOn":true}"#,
+            r#"{"tempo":1.04}"#,
+            r#"{"resultingKey":22}"#,
+            r#"{"elapsedTime":10.5,"nextCuePos":null,"isPlaying":true}"#,
+            r#"{"elapsedTime":11.5,"nextCuePos":64.25}"#,
+        ] {
+            assert_eq!(
+                server.post("/updateDeck/A", body).await,
+                StatusCode::OK,
+                "{}",
+                body
+            );
+        }
+
+        let deck = server.deck(DeckID::A);
+        assert_eq!(
+            deck.playback,
+            PlaybackState {
+                is_playing: true,
+                is_synced: true,
+                is_key_lock_on: true,
+                tempo: 1.04,
+                elapsed_time: 11.5,
+                next_cue_pos: Some(64.25),
+            }
+        );
+        assert_eq!(deck.content.unwrap().resulting_key, "Bbm");
+    }
+
+    #[actix_web::test]
+    async fn test_update_deck_ambiguous() {
+        let server = TestServer::new();
+
+        // The playing state is a superset of the elapsed time update, stopping
+        // has to be applied and not be mistaken for a position update
+        server
+            .post(
+                "/updateDeck/A",
+                r#"{"elapsedTime":5,"nextCuePos":null,"isPlaying":true}"#,
+            )
+            .await;
+        server
+            .post(
+                "/updateDeck/A",
+                r#"{"elapsedTime":6,"nextCuePos":null,"isPlaying":false}"#,
+            )
+            .await;
+        let playback = server.deck(DeckID::A).playback;
+        assert!(!playback.is_playing);
+        assert_eq!(playback.elapsed_time, 6.0);
+
+        // A missing cue point is the same as none
+        server.post("/updateDeck/A", r#"{"elapsedTime":7}"#).await;
+        let playback = server.deck(DeckID::A).playback;
+        assert_eq!(playback.elapsed_time, 7.0);
+        assert_eq!(playback.next_cue_pos, None);
+
+        // The first matching variant wins, the remaining fields are ignored
+        server
+            .post("/updateDeck/A", r#"{"isSynced":true,"tempo":1.5}"#)
+            .await;
+        let playback = server.deck(DeckID::A).playback;
+        assert!(playback.is_synced);
+        assert_eq!(playback.tempo, 1.0);
+    }
+
+    #[actix_web::test]
+    async fn test_update_deck_rejected() {
+        let server = TestServer::new();
+
+        for body in [
+            // isPlaying is always sent together with the position
+            r#"{"isPlaying":true}"#,
+            r#"{"isSynced":"yes"}"#,
+            r#"{"tempo":null}"#,
+            r#"{}"#,
+            "not json",
+        ] {
+            assert_eq!(
+                server.post("/updateDeck/A", body).await,
+                StatusCode::BAD_REQUEST,
+                "{}",
+                body
+            );
+        }
+        assert_eq!(server.deck(DeckID::A).playback, PlaybackState::default());
+    }
+
+    #[actix_web::test]
+    async fn test_update_deck_loop() {
+        let server = TestServer::new();
+        assert_eq!(
+            server
+                .post(
+                    "/updateDeck/C",
+                    r#"{"isLoopActive":true,"loopSize":0.5,"beatjumpSize":16}"#
+                )
+                .await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            server.deck(DeckID::C).r#loop,
+            LoopState {
+                is_active: true,
+                size: Some(0.5),
+                beatjump_size: Some(16.0),
+            }
+        );
+
+        // The size stays when the loop is turned off
+        server
+            .post(
+                "/updateDeck/C",
+                r#"{"isLoopActive":false,"loopSize":0.5,"beatjumpSize":null}"#,
+            )
+            .await;
+        let r#loop = server.deck(DeckID::C).r#loop;
+        assert!(!r#loop.is_active);
+        assert_eq!(r#loop.size, Some(0.5));
+        assert_eq!(r#loop.beatjump_size, None);
+    }
+
+    #[actix_web::test]
+    async fn test_update_hotcue() {
+        let server = TestServer::new();
+        let hotcue = r#"{"type":5,"position":32.5,"length":3.75,"name":"Drop"}"#;
+        assert_eq!(
+            server.post("/updateHotcue/B/3", hotcue).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            server.deck(DeckID::B).hotcues[2],
+            Some(Hotcue {
+                r#type: HotcueType::Loop,
+                position: 32.5,
+                length: 3.75,
+                name: "Drop".to_string(),
+            })
+        );
+
+        // Deleted in traktor
+        assert_eq!(
+            server.post("/updateHotcue/B/3", "null").await,
+            StatusCode::OK
+        );
+        assert!(server.deck(DeckID::B).hotcues.iter().all(Option::is_none));
+
+        assert_eq!(
+            server.post("/updateHotcue/B/9", hotcue).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            server.post("/updateHotcue/E/1", hotcue).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            server
+                .post(
+                    "/updateHotcue/B/1",
+                    r#"{"type":7,"position":0,"length":0,"name":""}"#
+                )
+                .await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_update_focus() {
+        let server = TestServer::new();
+        server
+            .post(
+                "/updateHotcue/A/1",
+                r#"{"type":0,"position":1,"length":0,"name":""}"#,
+            )
+            .await;
+        server
+            .post(
+                "/updateHotcue/C/8",
+                r#"{"type":3,"position":0,"length":0,"name":""}"#,
+            )
+            .await;
+        assert!(server.state().focused_deck().is_none());
+
+        // Until the focus is known the master deck is shown
+        server
+            .post("/updateMasterClock", r#"{"deck":"A","bpm":124}"#)
+            .await;
+        assert_eq!(server.state().focused_deck().unwrap().id, DeckID::A);
+
+        assert_eq!(
+            server.post("/updateFocus", r#"{"deck":"C"}"#).await,
+            StatusCode::OK
+        );
+        let state = server.state();
+        let deck = state.focused_deck().unwrap();
+        assert_eq!(deck.id, DeckID::C);
+        assert_eq!(deck.hotcues[7].as_ref().unwrap().r#type, HotcueType::Load);
+
+        assert_eq!(
+            server.post("/updateFocus", r#"{"deck":"E"}"#).await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_update_channel() {
+        let server = TestServer::new();
+
+        assert_eq!(
+            server.post("/updateChannel/2", r#"{"isOnAir":true}"#).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            server
+                .post("/updateChannel/2", r#"{"onAirLevel":0.75}"#)
+                .await,
+            StatusCode::OK
+        );
+        // The level is an integer at the ends of the fader
+        assert_eq!(
+            server.post("/updateChannel/3", r#"{"onAirLevel":1}"#).await,
+            StatusCode::OK
+        );
+
+        let state = server.state();
+        let channel = &state.channels[1];
+        assert!(channel.is_on_air);
+        assert_eq!(channel.on_air_level, 0.75);
+        assert_eq!(state.channels[2].on_air_level, 1.0);
+        assert!(state.is_deck_on_air(&DeckID::B));
+        assert!(!state.is_deck_on_air(&DeckID::C));
+    }
+
+    #[actix_web::test]
+    async fn test_update_channel_mixer() {
+        let server = TestServer::new();
+
+        // Captured from ApiChannel.qml mixerChangedTimer
+        assert_eq!(
+            server
+                .post(
+                    "/updateChannel/2",
+                    r#"{"gain":0.62,"eqHigh":0.5,"eqMid":0.41,"eqLow":0,"filter":0.3,"isFilterOn":true}"#,
+                )
+                .await,
+            StatusCode::OK
+        );
+        let state = server.state();
+        assert_eq!(
+            state.channels[1].mixer,
+            MixerKnobs {
+                gain: 0.62,
+                eq_high: 0.5,
+                eq_mid: 0.41,
+                eq_low: 0.0,
+                filter: 0.3,
+                is_filter_on: true,
+            }
+        );
+        // Untouched channels stay centered
+        assert_eq!(state.channels[0].mixer, MixerKnobs::default());
+
+        // The knobs are only sent together
+        assert_eq!(
+            server.post("/updateChannel/2", r#"{"eqLow":0.5}"#).await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_update_master_clock() {
+        let server = TestServer::new();
+
+        assert_eq!(
+            server
+                .post("/updateMasterClock", r#"{"deck":"B","bpm":124.5}"#)
+                .await,
+            StatusCode::OK
+        );
+        let state = server.state();
+        assert!(state.is_master_deck(&DeckID::B));
+        assert_eq!(state.master_clock().bpm, 124.5);
+
+        // Internal clock as master
+        server
+            .post("/updateMasterClock", r#"{"deck":null,"bpm":120}"#)
+            .await;
+        let state = server.state();
+        assert_eq!(state.master_clock().deck, None);
+        assert_eq!(state.master_clock().bpm, 120.0);
+    }
+
+    #[actix_web::test]
+    async fn test_fx() {
+        let server = TestServer::new();
+
+        for body in [FX_TYPE, FX_SELECT, FX_DRY_WET, FX_KNOB, FX_NAME, FX_PARAM] {
+            assert_eq!(server.post("/fx/1", body).await, StatusCode::OK, "{}", body);
+        }
+
+        let state = server.state();
+        let unit = &state.fx_units[0];
+        assert!(matches!(unit.r#type, FxUnitType::Group));
+
+        let dry_wet = &unit.knobs[0];
+        assert_eq!(dry_wet.value, 1.0);
+        assert_eq!(dry_wet.value_description, "100%");
+        assert_eq!(dry_wet.fx_name, "D/W");
+
+        // The param of the first knob arrived after its value
+        assert_eq!(unit.knobs[1].value, 0.5019609928131104);
+
+        // Selecting the effect names the knob, the name event overrides it
+        assert_eq!(unit.knobs[3].fx_name, "LEN");
+
+        // The other units are untouched
+        assert!(matches!(state.fx_units[1].r#type, FxUnitType::UNK));
+    }
+
+    #[actix_web::test]
+    async fn test_fx_select_names_knob() {
+        let server = TestServer::new();
+        server.post("/fx/2", FX_SELECT).await;
+        assert_eq!(server.state().fx_units[1].knobs[3].fx_name, "Auto Bouncer");
+    }
+
+    #[actix_web::test]
+    async fn test_fx_rejected() {
+        let server = TestServer::new();
+
+        for body in [
+            r#"{"Unknown": {"path": "app.traktor.fx.1.knobs.1"}}"#,
+            r#"{"Knob": {"path": "app.traktor.fx.1.knobs.1"}}"#,
+            "{}",
+        ] {
+            assert_eq!(
+                server.post("/fx/1", body).await,
+                StatusCode::BAD_REQUEST,
+                "{}",
+                body
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_restored_state_stays_stale() {
+        let mut state = TraktorState::default();
+        state.mark_stale();
+        let server = TestServer {
+            store: Arc::new(StateStore::new(state)),
+            ..TestServer::new()
+        };
+
+        // Traktor is connected but hasn't sent the decks yet
+        server
+            .post("/updateMasterClock", r#"{"deck":"A","bpm":120}"#)
+            .await;
+        assert_eq!(
+            server.post("/updateHotcue/B/1", "null").await,
+            StatusCode::OK
+        );
+        assert!(server.deck(DeckID::B).stale);
+
+        // Only the deck and unit which were sent are confirmed
+        server.post("/updateDeck/A", r#"{"tempo":1}"#).await;
+        server.post("/fx/2", FX_KNOB).await;
+        let state = server.state();
+        assert!(!state.decks[0].stale);
+        assert!(state.decks[1].stale);
+        assert!(state.fx_units[0].stale);
+        assert!(!state.fx_units[1].stale);
+    }
+
+    #[actix_web::test]
+    async fn test_remaining_time() {
+        let server = TestServer::new();
+        server.post("/deckLoaded/A", DECK_LOADED).await;
+        server
+            .post(
+                "/updateDeck/A",
+                r#"{"elapsedTime":400,"nextCuePos":null,"isPlaying":false}"#,
+            )
+            .await;
+
+        let now = Instant::now();
+        let remaining = server.deck(DeckID::A).remaining_at(now).unwrap();
+        assert!((remaining - 12.2).abs() < 1e-9);
+        assert_eq!(server.deck(DeckID::B).remaining_at(now), None);
+    }
+}
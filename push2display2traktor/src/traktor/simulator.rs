@@ -0,0 +1,450 @@
+use serde_json::{json, Value};
+use std::{f64::consts::PI, io, time::Duration};
+
+use crate::traktor::ApiClient;
+
+// Seconds between two steps, same as the debounce timers in the qml
+const TICK: f64 = 0.25;
+// How long a track plays alone before the next one is mixed in
+const PLAY_TIME: f64 = 40.0;
+const FADE_TIME: f64 = 16.0;
+// Every how many seconds one of the effects is swapped
+const FX_CHANGE_TIME: f64 = 20.0;
+const MASTER_BPM: f64 = 124.0;
+
+struct Track {
+    title: &'static str,
+    artist: &'static str,
+    album: &'static str,
+    genre: &'static str,
+    label: &'static str,
+    key: &'static str,
+    key_text: &'static str,
+    bpm: f64,
+    length: f64,
+    grid_offset: f64,
+}
+
+const TRACKS: [Track; 4] = [
+    Track {
+        title: "Night Drive",
+        artist: "Simulated Artist",
+        album: "Test Pressing",
+        genre: "House",
+        label: "Fake Records",
+        key: "Am",
+        key_text: "8A",
+        bpm: 122.0,
+        length: 372.4,
+        grid_offset: 0.084,
+    },
+    Track {
+        title: "Second Wind",
+        artist: "The Placeholders",
+        album: "Lorem Ipsum EP",
+        genre: "Tech House",
+        label: "Fake Records",
+        key: "Em",
+        key_text: "9A",
+        bpm: 125.0,
+        length: 401.0,
+        grid_offset: 0.012,
+    },
+    Track {
+        title: "Warehouse Tool",
+        artist: "Dummy Data",
+        album: "Tools Vol. 1",
+        genre: "Techno",
+        label: "Mock Audio",
+        key: "C",
+        key_text: "8B",
+        bpm: 128.0,
+        length: 355.8,
+        grid_offset: 0.231,
+    },
+    Track {
+        title: "Sunrise Edit",
+        artist: "Simulated Artist",
+        album: "Test Pressing",
+        genre: "Deep House",
+        label: "Mock Audio",
+        key: "Dm",
+        key_text: "7A",
+        bpm: 120.0,
+        length: 428.2,
+        grid_offset: 0.0,
+    },
+];
+
+// Effects and the name of their first parameter, the index is the
+// value traktor sends for a select
+const EFFECTS: [(&str, &str); 8] = [
+    ("No Effect", ""),
+    ("Delay", "FILTER"),
+    ("Reverb", "SIZE"),
+    ("Flanger", "DETUNE"),
+    ("Gater", "NOISE"),
+    ("Beatmasher 2", "ROTAT"),
+    ("Phaser", "DETUNE"),
+    ("Filter:92", "FILTER"),
+];
+
+const FX_TYPES: [&str; 3] = ["Group", "Single", "Pattern Player"];
+
+type Request = (String, Value);
+
+struct SimulatedDeck {
+    letter: char,
+    track: usize,
+    elapsed: f64,
+    is_playing: bool,
+}
+
+impl SimulatedDeck {
+    fn tempo(&self) -> f64 {
+        MASTER_BPM / TRACKS[self.track].bpm
+    }
+}
+
+/// Produces the requests traktor would send while two decks are mixed
+/// into each other, the knobs of the first fx unit sweep and its effects
+/// change every now and then.
+///
+/// The json matches what ApiDeck.qml, ApiChannel.qml, ApiMasterClock.qml
+/// and ApiFx.qml send.
+struct Simulator {
+    ticks: u64,
+    decks: [SimulatedDeck; 2],
+    // The deck playing alone or fading out
+    active: usize,
+    next_track: usize,
+    mix_start: u64,
+    fx_slots: [usize; 3],
+}
+
+fn ticks(secs: f64) -> u64 {
+    (secs / TICK) as u64
+}
+
+impl Simulator {
+    fn new() -> Self {
+        Self {
+            ticks: 0,
+            decks: [
+                SimulatedDeck {
+                    letter: 'A',
+                    track: 0,
+                    elapsed: TRACKS[0].grid_offset,
+                    is_playing: true,
+                },
+                SimulatedDeck {
+                    letter: 'B',
+                    track: 1,
+                    elapsed: TRACKS[1].grid_offset,
+                    is_playing: false,
+                },
+            ],
+            active: 0,
+            next_track: 2,
+            mix_start: 0,
+            fx_slots: [1, 2, 3],
+        }
+    }
+
+    fn time(&self) -> f64 {
+        self.ticks as f64 * TICK
+    }
+
+    /// Everything traktor sends after the D2 api was loaded
+    fn start(&self) -> Vec<Request> {
+        let mut requests = vec![];
+        for deck in 0..self.decks.len() {
+            requests.push(self.deck_loaded(deck));
+        }
+        requests.push(self.master_clock());
+        requests.push(channel(1, json!({"isOnAir": true})));
+        requests.push(channel(1, json!({"onAirLevel": 1.0})));
+        requests.push(channel(2, json!({"isOnAir": false})));
+        requests.push(channel(2, json!({"onAirLevel": 0.0})));
+        requests.push(channel(1, mixer(0.5)));
+        requests.push(channel(2, mixer(0.0)));
+
+        for unit in 1..=2 {
+            let path = format!("app.traktor.fx.{}.type", unit);
+            requests.push(fx(unit, "Type", discrete(&path, 0, &FX_TYPES)));
+            requests.push(fx(unit, "DryWet", self.dry_wet(unit)));
+            for slot in 1..=3 {
+                requests.extend(self.select(unit, slot, self.fx_slots[slot - 1]));
+            }
+        }
+        requests
+    }
+
+    /// Advances the simulation by one tick
+    fn tick(&mut self) -> Vec<Request> {
+        self.ticks += 1;
+        let mut requests = vec![];
+
+        for deck in self.decks.iter_mut().filter(|d| d.is_playing) {
+            deck.elapsed += TICK * deck.tempo();
+        }
+        // Traktor only sends the position once a second while playing
+        if self.ticks.is_multiple_of(ticks(1.0)) {
+            for deck in self.decks.iter().filter(|d| d.is_playing) {
+                requests.push(update_deck(
+                    deck.letter,
+                    json!({"elapsedTime": deck.elapsed, "nextCuePos": null}),
+                ));
+            }
+        }
+
+        self.mix(&mut requests);
+        self.sweep_fx(&mut requests);
+        requests
+    }
+
+    fn mix(&mut self, requests: &mut Vec<Request>) {
+        let step = self.ticks - self.mix_start;
+        let incoming = 1 - self.active;
+        let outgoing = self.active;
+        let (play, fade) = (ticks(PLAY_TIME), ticks(FADE_TIME));
+
+        if step == play {
+            let deck = &mut self.decks[incoming];
+            deck.is_playing = true;
+            requests.push(update_deck(deck.letter, json!({"isSynced": true})));
+            requests.push(update_deck(deck.letter, json!({"tempo": deck.tempo()})));
+            requests.push(update_deck(
+                deck.letter,
+                json!({"elapsedTime": deck.elapsed, "nextCuePos": null, "isPlaying": true}),
+            ));
+            requests.push(channel(incoming as u8 + 1, json!({"isOnAir": true})));
+        }
+
+        if step > play && step <= play + fade {
+            let level = (step - play) as f64 / fade as f64;
+            requests.push(channel(incoming as u8 + 1, json!({"onAirLevel": level})));
+            requests.push(channel(
+                outgoing as u8 + 1,
+                json!({"onAirLevel": 1.0 - level}),
+            ));
+            // Swap the bass along with the fade
+            requests.push(channel(incoming as u8 + 1, mixer(0.5 * level)));
+            requests.push(channel(outgoing as u8 + 1, mixer(0.5 * (1.0 - level))));
+        }
+
+        if step == play + fade {
+            let deck = &mut self.decks[outgoing];
+            deck.is_playing = false;
+            requests.push(update_deck(
+                deck.letter,
+                json!({"elapsedTime": deck.elapsed, "nextCuePos": null, "isPlaying": false}),
+            ));
+            requests.push(channel(outgoing as u8 + 1, json!({"isOnAir": false})));
+
+            // Load the next track into the deck which just stopped
+            deck.track = self.next_track;
+            deck.elapsed = TRACKS[deck.track].grid_offset;
+            self.next_track = (self.next_track + 1) % TRACKS.len();
+            self.active = incoming;
+            self.mix_start = self.ticks;
+            requests.push(self.master_clock());
+            requests.push(self.deck_loaded(outgoing));
+        }
+    }
+
+    fn sweep_fx(&mut self, requests: &mut Vec<Request>) {
+        // One knob of the first unit per tick, each with its own speed
+        let knob = (self.ticks % 3) as usize + 1;
+        let value = 0.5 + 0.5 * (2.0 * PI * self.time() / (4.0 + 3.0 * knob as f64)).sin();
+        let path = format!("app.traktor.fx.1.knobs.{}", knob);
+        requests.push(fx(1, "Knob", continuous(&path, value, "float")));
+
+        if self.ticks.is_multiple_of(ticks(1.0)) {
+            requests.push(fx(1, "DryWet", self.dry_wet(1)));
+        }
+
+        if self.ticks.is_multiple_of(ticks(FX_CHANGE_TIME)) {
+            let slot = (self.ticks / ticks(FX_CHANGE_TIME)) as usize % 3 + 1;
+            let effect = (self.fx_slots[slot - 1] + 1) % EFFECTS.len();
+            self.fx_slots[slot - 1] = effect;
+            requests.extend(self.select(1, slot, effect));
+        }
+    }
+
+    fn dry_wet(&self, unit: u8) -> Value {
+        let value = 0.6 + 0.4 * (2.0 * PI * self.time() / 30.0).sin();
+        let path = format!("app.traktor.fx.{}.dry_wet", unit);
+        continuous(&path, value, &format!("{:.0}%", value * 100.0))
+    }
+
+    // Selecting an effect also renames the knob of its slot
+    fn select(&self, unit: u8, slot: usize, effect: usize) -> Vec<Request> {
+        let names: Vec<&str> = EFFECTS.iter().map(|(name, _)| *name).collect();
+        let path = format!("app.traktor.fx.{}.select.{}", unit, slot);
+        let name_path = format!("app.traktor.fx.{}.knobs.{}.name", unit, slot);
+        vec![
+            fx(unit, "Select", discrete(&path, effect, &names)),
+            fx(unit, "Name", name(&name_path, EFFECTS[effect].1)),
+        ]
+    }
+
+    fn master_clock(&self) -> Request {
+        (
+            "/updateMasterClock".to_string(),
+            json!({"deck": self.decks[self.active].letter.to_string(), "bpm": MASTER_BPM}),
+        )
+    }
+
+    fn deck_loaded(&self, deck: usize) -> Request {
+        let deck = &self.decks[deck];
+        let track = &TRACKS[deck.track];
+        (
+            format!("/deckLoaded/{}", deck.letter),
+            json!({
+                "filePath": format!("/Volumes/Music/{} - {}.mp3", track.artist, track.title),
+                "title": track.title,
+                "artist": track.artist,
+                "album": track.album,
+                "genre": track.genre,
+                "comment": "",
+                "comment2": "",
+                "label": track.label,
+                "mix": "Original Mix",
+                "remixer": "",
+                "key": track.key,
+                "keyText": track.key_text,
+                "gridOffset": track.grid_offset,
+                "trackLength": track.length,
+                "elapsedTime": deck.elapsed,
+                "nextCuePos": null,
+                "bpm": track.bpm,
+                "tempo": deck.tempo(),
+                "resultingKey": track.key,
+                "isPlaying": deck.is_playing,
+                "isSynced": true,
+                "isKeyLockOn": false,
+            }),
+        )
+    }
+}
+
+fn update_deck(letter: char, body: Value) -> Request {
+    (format!("/updateDeck/{}", letter), body)
+}
+
+// All knobs centered but the low eq
+fn mixer(eq_low: f64) -> Value {
+    json!({
+        "gain": 0.5,
+        "eqHigh": 0.5,
+        "eqMid": 0.5,
+        "eqLow": eq_low,
+        "filter": 0.5,
+        "isFilterOn": false,
+    })
+}
+
+fn channel(id: u8, body: Value) -> Request {
+    (format!("/updateChannel/{}", id), body)
+}
+
+// ApiFx.qml wraps the app property in an object named after its kind
+fn fx(unit: u8, kind: &str, property: Value) -> Request {
+    (format!("/fx/{}", unit), json!({ kind: property }))
+}
+
+fn continuous(path: &str, value: f64, description: &str) -> Value {
+    json!({
+        "objectName": "",
+        "path": path,
+        "value": value,
+        "description": description,
+        "enabled": true,
+        "valueRange": {"objectName": "", "min": 0, "max": 1, "def": 0, "steps": 0, "type": "Continuous", "isFull": false, "isContinuous": true, "isDiscrete": false},
+        "valuesDescription": [],
+    })
+}
+
+fn discrete(path: &str, value: usize, values: &[&str]) -> Value {
+    json!({
+        "objectName": "",
+        "path": path,
+        "value": value,
+        "description": values[value],
+        "enabled": true,
+        "valueRange": {"objectName": "", "min": 0, "max": values.len() - 1, "def": 0, "steps": values.len(), "type": "Discrete", "isFull": false, "isContinuous": false, "isDiscrete": true},
+        "valuesDescription": values,
+    })
+}
+
+fn name(path: &str, name: &str) -> Value {
+    json!({
+        "objectName": "",
+        "path": path,
+        "value": name,
+        "description": name,
+        "enabled": true,
+        "valueRange": {"objectName": "", "min": "", "max": "", "def": "", "steps": 0, "type": "Full", "isFull": true, "isContinuous": false, "isDiscrete": false},
+        "valuesDescription": [],
+    })
+}
+
+async fn send(client: &ApiClient, requests: Vec<Request>) -> io::Result<()> {
+    for (path, body) in requests {
+        let status = client.post(&path, &body.to_string()).await?;
+        if status != 200 {
+            println!("{} was answered with {}", path, status);
+        }
+    }
+    Ok(())
+}
+
+/// Posts simulated traktor traffic to a running driver until stopped
+pub async fn simulate(client: &ApiClient) -> io::Result<()> {
+    let mut simulator = Simulator::new();
+    println!("Simulating traktor, stop with ctrl+c");
+    send(client, simulator.start()).await?;
+
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(TICK));
+    loop {
+        interval.tick().await;
+        send(client, simulator.tick()).await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::traktor::test_server::TestServer;
+    use crate::traktor::DeckID;
+
+    #[actix_web::test]
+    async fn test_simulated_requests_are_accepted() {
+        let server = TestServer::new();
+
+        // Long enough for one full mix from deck A to B
+        let mut simulator = Simulator::new();
+        let mut requests = simulator.start();
+        for _ in 0..ticks(PLAY_TIME + FADE_TIME + 1.0) {
+            requests.extend(simulator.tick());
+        }
+
+        for (path, body) in requests {
+            let status = server.post(&path, &body.to_string()).await;
+            assert!(status.is_success(), "{} {}", path, body);
+        }
+
+        let state = server.state();
+        assert!(state.is_master_deck(&DeckID::B));
+        assert!(state.is_deck_on_air(&DeckID::B));
+        assert!(!state.is_deck_on_air(&DeckID::A));
+        let deck_a = &state.decks[0];
+        assert!(!deck_a.playback.is_playing);
+        assert_eq!(deck_a.content.as_ref().unwrap().title, TRACKS[2].title);
+        assert!(state.decks[1].playback.is_playing);
+        // Swapped to a flanger after 20s, its first parameter is named by the Name event
+        assert_eq!(state.fx_units[0].knobs[2].fx_name, "DETUNE");
+    }
+}
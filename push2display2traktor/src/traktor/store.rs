@@ -0,0 +1,208 @@
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    watch,
+};
+
+use super::{events::StateEvent, TraktorState};
+
+/// Parts of the traktor state which change independently
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Section {
+    FxUnits,
+    Decks,
+    Channels,
+    MasterClock,
+    Client,
+}
+
+/// Change counter per section, bumped on every update
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Versions {
+    fx_units: u64,
+    decks: u64,
+    channels: u64,
+    master_clock: u64,
+    client: u64,
+}
+
+impl Versions {
+    fn bump(&mut self, section: Section) {
+        let version = match section {
+            Section::FxUnits => &mut self.fx_units,
+            Section::Decks => &mut self.decks,
+            Section::Channels => &mut self.channels,
+            Section::MasterClock => &mut self.master_clock,
+            Section::Client => &mut self.client,
+        };
+        *version += 1;
+    }
+
+    /// Sections which changed compared to a previously seen version
+    pub fn changes_since(&self, seen: &Versions) -> Changes {
+        Changes {
+            fx_units: self.fx_units != seen.fx_units,
+            decks: self.decks != seen.decks,
+            channels: self.channels != seen.channels,
+            master_clock: self.master_clock != seen.master_clock,
+            client: self.client != seen.client,
+        }
+    }
+}
+
+/// Dirty flags per section
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Changes {
+    pub fx_units: bool,
+    pub decks: bool,
+    pub channels: bool,
+    pub master_clock: bool,
+    pub client: bool,
+}
+
+impl Changes {
+    pub fn all() -> Self {
+        Self {
+            fx_units: true,
+            decks: true,
+            channels: true,
+            master_clock: true,
+            client: true,
+        }
+    }
+}
+
+/// Holds the traktor state and notifies about changes.
+///
+/// The render loop watches the state and only clones it if the version
+/// changed, all others can subscribe to the individual change events.
+/// Updates are synchronous and only hold the lock for the mutation
+/// itself, so the handlers never wait for a frame to finish.
+pub struct StateStore {
+    state: watch::Sender<TraktorState>,
+    events: broadcast::Sender<StateEvent>,
+}
+
+impl Default for StateStore {
+    fn default() -> Self {
+        Self::new(TraktorState::default())
+    }
+}
+
+impl StateStore {
+    pub fn new(state: TraktorState) -> Self {
+        let (state, _) = watch::channel(state);
+        let (events, _) = broadcast::channel(256);
+        Self { state, events }
+    }
+
+    /// Applies a mutation to one section of the state.
+    ///
+    /// The closure returns the resulting change events, if there are none
+    /// the state is considered unchanged and nobody is notified.
+    /// Returns true if the state changed.
+    pub fn update<F, E>(&self, section: Section, f: F) -> bool
+    where
+        F: FnOnce(&mut TraktorState) -> E,
+        E: IntoIterator<Item = StateEvent>,
+    {
+        let mut changes = vec![];
+        self.state.send_if_modified(|state| {
+            changes.extend(f(state));
+            if changes.is_empty() {
+                return false;
+            }
+            state.versions.bump(section);
+            true
+        });
+
+        let changed = !changes.is_empty();
+        for change in changes {
+            self.publish(change);
+        }
+        changed
+    }
+
+    /// A copy of the current state
+    pub fn snapshot(&self) -> TraktorState {
+        self.state.borrow().clone()
+    }
+
+    pub fn snapshot_event(&self) -> StateEvent {
+        StateEvent::Snapshot {
+            state: self.snapshot(),
+        }
+    }
+
+    /// Receiver which is marked as changed after every update
+    pub fn watch(&self) -> watch::Receiver<TraktorState> {
+        self.state.subscribe()
+    }
+
+    /// Notify all subscribers about a change, fine if there are none
+    pub fn publish(&self, event: StateEvent) {
+        let _ = self.events.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<StateEvent> {
+        self.events.subscribe()
+    }
+
+    /// The next event for a subscriber, a full snapshot instead if it was
+    /// too slow to keep up. None once the store is dropped
+    pub async fn next_event(
+        &self,
+        events: &mut broadcast::Receiver<StateEvent>,
+    ) -> Option<StateEvent> {
+        match events.recv().await {
+            Ok(event) => Some(event),
+            Err(RecvError::Lagged(_)) => Some(self.snapshot_event()),
+            Err(RecvError::Closed) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::traktor::DeckID;
+
+    #[test]
+    fn test_update_bumps_section() {
+        let store = StateStore::default();
+        let mut watch = store.watch();
+        let mut events = store.subscribe();
+        let seen = watch.borrow_and_update().versions;
+
+        let changed = store.update(Section::Decks, |_| {
+            Some(StateEvent::KeyChanged {
+                deck: DeckID::A,
+                resulting_key: "Am".to_string(),
+            })
+        });
+
+        assert!(changed);
+        assert!(watch.has_changed().unwrap());
+        let changes = watch.borrow_and_update().versions.changes_since(&seen);
+        assert_eq!(
+            changes,
+            Changes {
+                decks: true,
+                ..Default::default()
+            }
+        );
+        assert!(matches!(events.try_recv(), Ok(StateEvent::KeyChanged { .. })));
+    }
+
+    #[test]
+    fn test_update_without_change() {
+        let store = StateStore::default();
+        let mut watch = store.watch();
+        watch.borrow_and_update();
+
+        let changed = store.update(Section::FxUnits, |_| None);
+
+        assert!(!changed);
+        assert!(!watch.has_changed().unwrap());
+    }
+}
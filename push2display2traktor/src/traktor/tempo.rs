@@ -0,0 +1,105 @@
+use crate::traktor::Deck;
+
+/// Tempo of a deck as traktor plays it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeckTempo {
+    // Original tempo of the track
+    pub base_bpm: f64,
+    // Effective tempo including the pitch
+    pub bpm: f64,
+    // Pitch in percent, 0 is the original tempo
+    pub pitch: f64,
+}
+
+/// How far a deck is off the master tempo
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TempoDifference {
+    // Effective bpm minus the master bpm
+    pub bpm: f64,
+    // Difference in percent of the master tempo
+    pub percent: f64,
+    // Pitch in percent the deck needs to play at the master tempo
+    pub required_pitch: f64,
+}
+
+impl DeckTempo {
+    /// None without a track or if the track has no tempo
+    pub fn of(deck: &Deck) -> Option<Self> {
+        let base_bpm = deck.content.as_ref()?.bpm;
+        if base_bpm <= 0.0 || !base_bpm.is_finite() {
+            return None;
+        }
+        let tempo = deck.playback.tempo;
+        Some(Self {
+            base_bpm,
+            bpm: base_bpm * tempo,
+            pitch: (tempo - 1.0) * 100.0,
+        })
+    }
+
+    /// None if there is no master tempo to compare with
+    pub fn difference_to(&self, master_bpm: f64) -> Option<TempoDifference> {
+        if master_bpm <= 0.0 || !master_bpm.is_finite() {
+            return None;
+        }
+        Some(TempoDifference {
+            bpm: self.bpm - master_bpm,
+            percent: (self.bpm / master_bpm - 1.0) * 100.0,
+            required_pitch: (master_bpm / self.base_bpm - 1.0) * 100.0,
+        })
+    }
+}
+
+impl TempoDifference {
+    /// True if the pitch fader can't reach the master tempo, `range`
+    /// being the fader range in percent, e.g. 8 for +-8%
+    pub fn is_out_of_range(&self, range: f64) -> bool {
+        self.required_pitch.abs() > range
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::traktor::{DeckContent, DeckID};
+
+    fn deck(bpm: f64, tempo: f64) -> Deck {
+        let mut deck = Deck::new(DeckID::A);
+        deck.content = Some(DeckContent::for_test("Title", bpm, 300.0));
+        deck.playback.tempo = tempo;
+        deck
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_deck_tempo() {
+        let tempo = DeckTempo::of(&deck(120.0, 1.05)).unwrap();
+        assert_close(tempo.bpm, 126.0);
+        assert_close(tempo.pitch, 5.0);
+
+        assert_eq!(DeckTempo::of(&deck(0.0, 1.0)), None);
+        assert_eq!(DeckTempo::of(&Deck::new(DeckID::B)), None);
+    }
+
+    #[test]
+    fn test_difference() {
+        // 120 bpm track pitched to 126, master at 125
+        let tempo = DeckTempo::of(&deck(120.0, 1.05)).unwrap();
+        let difference = tempo.difference_to(125.0).unwrap();
+        assert_close(difference.bpm, 1.0);
+        assert_close(difference.percent, 0.8);
+        assert_close(difference.required_pitch, 125.0 / 1.2 - 100.0);
+        assert!(!difference.is_out_of_range(8.0));
+        assert!(difference.is_out_of_range(4.0));
+
+        // A 100 bpm track can't get to 125 with +-8%
+        let tempo = DeckTempo::of(&deck(100.0, 1.08)).unwrap();
+        assert!(tempo.difference_to(125.0).unwrap().is_out_of_range(8.0));
+
+        assert_eq!(tempo.difference_to(0.0), None);
+    }
+}
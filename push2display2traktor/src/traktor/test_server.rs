@@ -0,0 +1,83 @@
+//! Runs requests through the routes and middlewares of the server, with
+//! payloads as sent by the D2 api in traktor, so changes in the qml can't
+//! silently break the parsing.
+
+use super::*;
+use actix_web::{
+    http::StatusCode,
+    test::{call_service, init_service, read_body, TestRequest},
+    App,
+};
+
+// Captured from ApiDeck.qml deckLoadedTimer
+pub const DECK_LOADED: &str = r#"{"filePath":"C:\\Music\\Artist - Title.mp3","title":"Title","artist":"Artist","album":"Album","genre":"Techno","comment":"","comment2":"","label":"Label","mix":"Original Mix","remixer":"","key":"Am","keyText":"8A","gridOffset":0.0467,"trackLength":412.2,"elapsedTime":0,"nextCuePos":null,"bpm":127.99,"tempo":1,"resultingKey":21.0399,"isPlaying":false,"isSynced":false,"isKeyLockOn":false}"#;
+
+// Captured from ApiFx.qml, see the comments above FxEvent in services.rs
+pub const FX_TYPE: &str = r#"{"Type": {"objectName": "", "path": "app.traktor.fx.1.type", "value": 0, "description": "Group", "enabled": true, "valueRange": {"objectName": "", "min": 0, "max": 2, "def": 0, "steps": 3, "type": "Discrete", "isFull": false, "isContinuous": false, "isDiscrete": true}, "valuesDescription": ["Group", "Single", "Pattern Player"]}}"#;
+pub const FX_SELECT: &str = r#"{"Select": {"objectName": "", "path": "app.traktor.fx.1.select.3", "value": 20, "description": "Auto Bouncer", "enabled": true, "valueRange": {"objectName": "", "min": 0, "max": 31, "def": 0, "steps": 32, "type": "Discrete", "isFull": false, "isContinuous": false, "isDiscrete": true}, "valuesDescription": ["No Effect", "Delay", "Reverb", "Flanger", "Gater", "Beatmasher 2", "Delay T3", "Filter:92", "Phaser", "Reverb T3", "Ringmodulator", "Digital LoFi", "Mulholland Drive", "Transpose Stretch", "BeatSlicer", "Formant Filter", "Bouncer", "Peak Filter", "Tape Delay", "Ramp Delay", "Auto Bouncer", "¶ WormHole", "¶ LaserSlicer", "¶ GranuPhase", "¶ Bass-o-Matic", "¶ PolarWind", "¶ EventHorizon", "¶ Zzzurp", "¶ FlightTest", "¶ Strrretch (Slow)", "¶ Strrretch (Fast)", "¶ DarkMatter"]}}"#;
+pub const FX_DRY_WET: &str = r#"{"DryWet": {"objectName": "", "path": "app.traktor.fx.1.dry_wet", "value": 1, "description": "100%", "enabled": true, "valueRange": {"objectName": "", "min": 0, "max": 1, "def": 0, "steps": 0, "type": "Continuous", "isFull": false, "isContinuous": true, "isDiscrete": false}, "valuesDescription": []}}"#;
+pub const FX_KNOB: &str = r#"{"Knob": {"objectName": "", "path": "app.traktor.fx.1.knobs.1", "value": 0, "description": "float", "enabled": true, "valueRange": {"objectName": "", "min": 0, "max": 1, "def": 0, "steps": 0, "type": "Continuous", "isFull": false, "isContinuous": true, "isDiscrete": false}, "valuesDescription": []}}"#;
+pub const FX_NAME: &str = r#"{"Name": {"objectName": "", "path": "app.traktor.fx.1.knobs.3.name", "value": "LEN", "description": "LEN", "enabled": true, "valueRange": {"objectName": "", "min": "", "max": "", "def": "", "steps": 0, "type": "Full", "isFull": true, "isContinuous": false, "isDiscrete": false}, "valuesDescription": []}}"#;
+pub const FX_PARAM: &str = r#"{"Param": {"objectName": "", "path": "app.traktor.fx.1.parameters.1", "value": 0.5019609928131104, "description": "0", "enabled": true, "valueRange": {"objectName": "", "min": -3.4028234663852886e+38, "max": 3.4028234663852886e+38, "def": 0, "steps": 0, "type": "Full", "isFull": true, "isContinuous": false, "isDiscrete": false}, "valuesDescription": []}}"#;
+
+/// A server without a socket, requests go straight to the handlers
+pub struct TestServer {
+    pub store: Arc<StateStore>,
+    pub metrics: Arc<Metrics>,
+    pub recorder: Option<Arc<Recorder>>,
+}
+
+impl TestServer {
+    pub fn new() -> Self {
+        Self {
+            store: Arc::new(StateStore::default()),
+            metrics: Arc::new(Metrics::default()),
+            recorder: None,
+        }
+    }
+
+    /// Handles the request with the same routes and middlewares as
+    /// `create_server`, returns the status and body
+    pub async fn call(&self, req: TestRequest) -> (StatusCode, String) {
+        let data = web::Data::new(AppState {
+            metrics: self.metrics.clone(),
+            recorder: self.recorder.clone(),
+            ..AppState::new(self.store.clone())
+        });
+        let app = init_service(
+            App::new()
+                .app_data(data)
+                .wrap(from_fn(record_requests))
+                .wrap(from_fn(count_events))
+                .wrap(from_fn(check_protocol))
+                .configure(routes),
+        )
+        .await;
+        let res = call_service(&app, req.to_request()).await;
+        let status = res.status();
+        let body = read_body(res).await;
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    /// Posts like ApiClient.js and returns the status
+    pub async fn post(&self, uri: &str, body: &str) -> StatusCode {
+        self.call(api_request(uri, body)).await.0
+    }
+
+    pub fn state(&self) -> TraktorState {
+        self.store.snapshot()
+    }
+
+    pub fn deck(&self, id: DeckID) -> Deck {
+        self.state().decks.into_iter().find(|d| d.id == id).unwrap()
+    }
+}
+
+/// A post as sent by ApiClient.js
+pub fn api_request(uri: &str, body: &str) -> TestRequest {
+    TestRequest::post()
+        .uri(uri)
+        .insert_header(("Content-Type", "application/json"))
+        .insert_header(("X-Push2Traktor-Protocol", PROTOCOL_VERSION.to_string()))
+        .set_payload(body.to_string())
+}
@@ -14,6 +14,13 @@ Item {
   AppProperty { id: propXfaderAssignRight;  path: pathPrefix + "xfader_assign.right";  onValueChanged: updateOnAirState() }
   AppProperty { id: propXfaderAdjust;       path: "app.traktor.mixer.xfader.adjust";   onValueChanged: updateOnAirState() }
 
+  AppProperty { id: propGain;               path: pathPrefix + "gain";                 onValueChanged: mixerChangedTimer.start() }
+  AppProperty { id: propEqHigh;             path: pathPrefix + "eq.high";              onValueChanged: mixerChangedTimer.start() }
+  AppProperty { id: propEqMid;              path: pathPrefix + "eq.mid";               onValueChanged: mixerChangedTimer.start() }
+  AppProperty { id: propEqLow;              path: pathPrefix + "eq.low";               onValueChanged: mixerChangedTimer.start() }
+  AppProperty { id: propFilter;             path: pathPrefix + "fx.adjust";            onValueChanged: mixerChangedTimer.start() }
+  AppProperty { id: propFilterOn;           path: pathPrefix + "fx.on";                onValueChanged: mixerChangedTimer.start() }
+
   Timer {
     id: onAirLevelChangedTimer
     interval: 250
@@ -34,6 +41,24 @@ Item {
     }
   }
 
+  // Not restarted on every change, so turning a knob sends
+  // its position every 100ms instead of only once it stops
+  Timer {
+    id: mixerChangedTimer
+    interval: 100
+
+    onTriggered: {
+      ApiClient.send("updateChannel/" + index, {
+        gain:       propGain.value,
+        eqHigh:     propEqHigh.value,
+        eqMid:      propEqMid.value,
+        eqLow:      propEqLow.value,
+        filter:     propFilter.value,
+        isFilterOn: propFilterOn.value,
+      })
+    }
+  }
+
   function updateOnAirState() {
     var isOnAir = propVolume.value > 0
       && ((!propXfaderAssignLeft.value && !propXfaderAssignRight.value)
@@ -1,5 +1,9 @@
 var API_BASE_URL = "http://127.0.0.1:8080"
 
+// Bump together with PROTOCOL_VERSION in the driver whenever
+// an endpoint or payload changes
+var PROTOCOL_VERSION = 4
+
 function send(endpoint, data) {
   var request = new XMLHttpRequest(),
       body    = JSON.stringify(data)
@@ -7,5 +11,13 @@ function send(endpoint, data) {
   request.open("POST", API_BASE_URL + "/" + endpoint, true)
   request.setRequestHeader("Content-Type", "application/json")
   request.setRequestHeader("Content-Length", body.length)
+  request.setRequestHeader("X-Push2Traktor-Protocol", PROTOCOL_VERSION)
   request.send(body)
 }
+
+function hello(traktorVersion) {
+  send("hello", {
+    protocolVersion: PROTOCOL_VERSION,
+    traktorVersion: traktorVersion,
+  })
+}
@@ -3,11 +3,19 @@ import QtQuick 2.0
 import "ApiClient.js" as ApiClient
 
 Item {
+  id: apiDeck
   property int       deckId:  0
 
   readonly property string    deckLetter:  String.fromCharCode(65 + deckId)
   readonly property string    pathPrefix:  "app.traktor.decks." + (deckId+1) + "."
 
+  // Loop sizes are indices into this list of beats
+  readonly property variant   loopSizes:   [1/32, 1/16, 1/8, 1/4, 1/2, 1, 2, 4, 8, 16, 32]
+  // Move sizes start with the fine beatjump, which has no length in beats,
+  // and end with one moving by the loop size
+  readonly property variant   moveSizes:   [null, 1/32, 1/16, 1/8, 1/4, 1/2, 1, 2, 4, 8, 16, 32]
+  readonly property int       moveSizeLoop: 12
+
   AppProperty { path: pathPrefix + "is_loaded";         onValueChanged: deckLoadedTimer.start() }
   AppProperty { path: pathPrefix + "is_loaded_signal";  onValueChanged: deckLoadedTimer.start() }
 
@@ -30,6 +38,9 @@ Item {
   AppProperty { id: propBpm;           path: pathPrefix + "tempo.base_bpm" }
   AppProperty { id: propTempo;         path: pathPrefix + "tempo.tempo_for_display";      onValueChanged: tempoChangedTimer.restart() }
   AppProperty { id: propResultingKey;  path: pathPrefix + "track.key.resulting.precise";  onValueChanged: keyChangedTimer.restart() }
+  AppProperty { id: propLoopActive;    path: pathPrefix + "loop.active";                  onValueChanged: updateLoop() }
+  AppProperty { id: propLoopSize;      path: pathPrefix + "loop.size";                    onValueChanged: updateLoop() }
+  AppProperty { id: propMoveSize;      path: pathPrefix + "move.size";                    onValueChanged: updateLoop() }
 
   AppProperty {
     id: propIsPlaying
@@ -128,6 +139,25 @@ Item {
     }
   }
 
+  ApiHotcue { deckLetter: apiDeck.deckLetter; deckPath: apiDeck.pathPrefix; index: 1 }
+  ApiHotcue { deckLetter: apiDeck.deckLetter; deckPath: apiDeck.pathPrefix; index: 2 }
+  ApiHotcue { deckLetter: apiDeck.deckLetter; deckPath: apiDeck.pathPrefix; index: 3 }
+  ApiHotcue { deckLetter: apiDeck.deckLetter; deckPath: apiDeck.pathPrefix; index: 4 }
+  ApiHotcue { deckLetter: apiDeck.deckLetter; deckPath: apiDeck.pathPrefix; index: 5 }
+  ApiHotcue { deckLetter: apiDeck.deckLetter; deckPath: apiDeck.pathPrefix; index: 6 }
+  ApiHotcue { deckLetter: apiDeck.deckLetter; deckPath: apiDeck.pathPrefix; index: 7 }
+  ApiHotcue { deckLetter: apiDeck.deckLetter; deckPath: apiDeck.pathPrefix; index: 8 }
+
+  function updateLoop() {
+    ApiClient.send("updateDeck/" + deckLetter, {
+      isLoopActive: propLoopActive.value,
+      loopSize: getBeats(loopSizes, propLoopSize.value),
+      beatjumpSize: (propMoveSize.value == moveSizeLoop)
+        ? getBeats(loopSizes, propLoopSize.value)
+        : getBeats(moveSizes, propMoveSize.value),
+    })
+  }
+
   function getFilePath() {
     if (!propFilePath.value) return ""
 
@@ -135,6 +165,9 @@ Item {
       ? propFilePath.value
       : "/Volumes/" + propFilePath.value.replace(/:/g, "/")
   }
+  function getBeats(sizes, index) {
+    return (index >= 0 && index < sizes.length) ? sizes[index] : null
+  }
   function getNextCuePos() {
     return (propNextCuePoint.value == -1) ? null : propNextCuePoint.value/1000
   }
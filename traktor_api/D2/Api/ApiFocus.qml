@@ -0,0 +1,16 @@
+import CSI 1.0
+import QtQuick 2.0
+import "ApiClient.js" as ApiClient
+
+Item {
+  property int       deckId:  0
+
+  onDeckIdChanged: updateFocus()
+  Component.onCompleted: updateFocus()
+
+  function updateFocus() {
+    ApiClient.send("updateFocus", {
+      deck: String.fromCharCode(65 + deckId),
+    })
+  }
+}
@@ -0,0 +1,15 @@
+import CSI 1.0
+import QtQuick 2.0
+import "ApiClient.js" as ApiClient
+
+Item {
+  // Repeated, the driver might be started after traktor
+  Timer {
+    interval: 10000
+    repeat: true
+    running: true
+    triggeredOnStart: true
+
+    onTriggered: ApiClient.hello(Qt.application.version || null)
+  }
+}
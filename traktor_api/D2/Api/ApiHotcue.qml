@@ -0,0 +1,32 @@
+import CSI 1.0
+import QtQuick 2.0
+import "ApiClient.js" as ApiClient
+
+Item {
+  property string    deckLetter:  "A"
+  property string    deckPath:    "app.traktor.decks.1."
+  property int       index:       1
+
+  readonly property string    pathPrefix:  deckPath + "track.cue.hotcues." + index + "."
+
+  AppProperty { id: propExists;    path: pathPrefix + "exists";     onValueChanged: hotcueChangedTimer.restart() }
+  AppProperty { id: propType;      path: pathPrefix + "type";       onValueChanged: hotcueChangedTimer.restart() }
+  AppProperty { id: propStartPos;  path: pathPrefix + "start_pos";  onValueChanged: hotcueChangedTimer.restart() }
+  AppProperty { id: propLength;    path: pathPrefix + "length";     onValueChanged: hotcueChangedTimer.restart() }
+  AppProperty { id: propName;      path: pathPrefix + "name";       onValueChanged: hotcueChangedTimer.restart() }
+
+  // Loading a track changes all properties at once
+  Timer {
+    id: hotcueChangedTimer
+    interval: 250
+
+    onTriggered: {
+      ApiClient.send("updateHotcue/" + deckLetter + "/" + index, !propExists.value ? null : {
+        type:      propType.value,
+        position:  propStartPos.value/1000,
+        length:    propLength.value/1000,
+        name:      propName.value,
+      })
+    }
+  }
+}
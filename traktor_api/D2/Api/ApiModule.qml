@@ -1,6 +1,9 @@
 import CSI 1.0
 
 Module {
+  // Set by D2.qml from the deck assignment and focus
+  property int focusedDeckId: 0
+
   ApiChannel { index: 1 }
   ApiChannel { index: 2 }
   ApiChannel { index: 3 }
@@ -11,6 +14,9 @@ Module {
   ApiDeck { deckId: 2 }
   ApiDeck { deckId: 3 }
 
+  ApiHello {}
+  ApiFocus { deckId: focusedDeckId }
+
   ApiMasterClock {}
 
   ApiFx  { fxUnitId: 1 }
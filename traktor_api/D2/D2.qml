@@ -9,7 +9,10 @@ import "./Api"
 
 Mapping
 {
-  ApiModule {}
+  // C or D if the deck button switched the focus
+  ApiModule {
+    focusedDeckId: (decksAssignment.value == DecksAssignment.AC ? 0 : 1) + (deck.deckFocus ? 2 : 0)
+  }
 
   //------------------------------------------------------------------------------------------------------------------
   // LOOP/BEATJUMP SIZE SETTINGS